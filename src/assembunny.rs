@@ -0,0 +1,742 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::num::ParseIntError;
+use std::ops::{Deref, Index, IndexMut};
+use std::str::FromStr;
+
+use thiserror::Error;
+
+mod arithmetic;
+mod assembler;
+pub mod cfg;
+mod compiler;
+mod debugger;
+mod decompiler;
+#[cfg(test)]
+mod fuzz;
+mod io;
+mod json;
+mod limit;
+mod optimizer;
+mod profiler;
+mod search;
+mod signal;
+mod snapshot;
+mod symbolic;
+mod trace;
+mod transpiler;
+
+pub use arithmetic::{Arithmetic, Overflow};
+pub use assembler::{AssembleError, AssembleErrorKind, assemble};
+pub use compiler::Backend;
+pub use debugger::{Debugger, StopReason, WatchCondition, Watchpoint};
+pub use decompiler::{Pseudocode, decompile};
+pub use io::{Network, NetworkOutcome, Sink};
+pub use limit::RunOutcome;
+pub use optimizer::{Op, optimize};
+pub use profiler::{BlockProfile, Profile};
+#[cfg(test)]
+pub(crate) use signal::BITS_PROGRAM;
+pub use signal::{Signal, find_signal_value};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use symbolic::{Expr, SymbolicError, SymbolicMachine};
+pub use trace::{Divergence, TraceError, TraceRecord, Tracer, diff, read_trace};
+pub use transpiler::{TranspileError, transpile};
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("Invalid instruction name or syntax")]
+    SyntaxError,
+    #[error("Invalid register name {name:?}{}", .line.map_or_else(String::new, |line| format!(" on line {line}")))]
+    InvalidRegister {
+        name: String,
+        /// One-based line number, when parsing a whole program.
+        line: Option<usize>,
+    },
+    #[error("Instruction {0:?} is not supported by this dialect")]
+    UnsupportedInstruction(String),
+    #[error(transparent)]
+    InvalidNumber(#[from] ParseIntError),
+}
+
+/// The instruction sets used by the different puzzles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// `cpy`, `inc`, `dec` and `jnz` (day 12)
+    Basic,
+    /// Basic, plus `tgl` (day 23)
+    Toggle,
+    /// Basic, plus `out` (day 25)
+    Clock,
+    /// Basic, plus `in` and `out`, for machines connected in a [`Network`]
+    Io,
+}
+
+impl Dialect {
+    #[must_use]
+    pub const fn supports(self, instruction: Instruction) -> bool {
+        match instruction {
+            Instruction::Cpy(..)
+            | Instruction::Inc(..)
+            | Instruction::Dec(..)
+            | Instruction::Jnz(..) => true,
+            Instruction::Tgl(..) => matches!(self, Self::Toggle),
+            Instruction::Out(..) => matches!(self, Self::Clock | Self::Io),
+            Instruction::In(..) => matches!(self, Self::Io),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Copy
+    Cpy(RegOrValue, RegOrValue),
+    /// Increase
+    Inc(RegOrValue),
+    /// Decrease
+    Dec(RegOrValue),
+    /// Jump if not zero
+    Jnz(RegOrValue, RegOrValue),
+    /// Toggle
+    Tgl(RegOrValue),
+    /// Output
+    Out(RegOrValue),
+    /// Input
+    In(RegOrValue),
+}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(' ').ok_or(ParseError::SyntaxError)? {
+            ("cpy", rest) => {
+                let (a, b) = rest.split_once(' ').ok_or(ParseError::SyntaxError)?;
+                Self::Cpy(a.parse()?, b.parse()?)
+            }
+            ("inc", rest) => Self::Inc(rest.parse()?),
+            ("dec", rest) => Self::Dec(rest.parse()?),
+            ("jnz", rest) => {
+                let (a, b) = rest.split_once(' ').ok_or(ParseError::SyntaxError)?;
+                Self::Jnz(a.parse()?, b.parse()?)
+            }
+            ("tgl", rest) => Self::Tgl(rest.parse()?),
+            ("out", rest) => Self::Out(rest.parse()?),
+            ("in", rest) => Self::In(rest.parse()?),
+            _ => return Err(ParseError::SyntaxError),
+        })
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Cpy(a, b) => write!(f, "cpy {a} {b}"),
+            Self::Inc(a) => write!(f, "inc {a}"),
+            Self::Dec(a) => write!(f, "dec {a}"),
+            Self::Jnz(a, b) => write!(f, "jnz {a} {b}"),
+            Self::Tgl(a) => write!(f, "tgl {a}"),
+            Self::Out(a) => write!(f, "out {a}"),
+            Self::In(a) => write!(f, "in {a}"),
+        }
+    }
+}
+
+impl Instruction {
+    /// The registers named by the operands.
+    pub fn registers(self) -> impl Iterator<Item = Reg> {
+        let (first, second) = match self {
+            Self::Cpy(a, b) | Self::Jnz(a, b) => (a, Some(b)),
+            Self::Inc(a) | Self::Dec(a) | Self::Tgl(a) | Self::Out(a) | Self::In(a) => (a, None),
+        };
+        [Some(first), second]
+            .into_iter()
+            .flatten()
+            .filter_map(|operand| match operand {
+                RegOrValue::Reg(reg) => Some(reg),
+                RegOrValue::Value(_) => None,
+            })
+    }
+
+    #[must_use]
+    pub const fn toggle(self) -> Self {
+        match self {
+            Self::Cpy(a, b) => Self::Jnz(a, b),
+            Self::Inc(a) => Self::Dec(a),
+            Self::Dec(a) | Self::Tgl(a) | Self::Out(a) | Self::In(a) => Self::Inc(a),
+            Self::Jnz(a, b) => Self::Cpy(a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegOrValue {
+    Reg(Reg),
+    Value(i64),
+}
+
+impl FromStr for RegOrValue {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.as_bytes() {
+            [b'0'..=b'9' | b'-', ..] => Self::Value(s.parse()?),
+            _ => Self::Reg(s.parse()?),
+        })
+    }
+}
+
+impl Display for RegOrValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reg(reg) => write!(f, "{reg}"),
+            Self::Value(val) => write!(f, "{val}"),
+        }
+    }
+}
+
+/// The longest register name.
+const MAX_NAME_LEN: usize = 16;
+
+/// A register, named by any lowercase identifier of up to 16 characters. The name is stored in
+/// the register itself, so programs parsed separately never share any state.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg([u8; MAX_NAME_LEN]);
+
+impl Reg {
+    pub const A: Self = Self::letter(b'a');
+    pub const B: Self = Self::letter(b'b');
+    pub const C: Self = Self::letter(b'c');
+    pub const D: Self = Self::letter(b'd');
+
+    const fn letter(letter: u8) -> Self {
+        let mut name = [0; MAX_NAME_LEN];
+        name[0] = letter;
+        Self(name)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        let len = self
+            .0
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(MAX_NAME_LEN);
+        std::str::from_utf8(&self.0[..len]).unwrap_or_default()
+    }
+}
+
+impl FromStr for Reg {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with(|c: char| c.is_ascii_lowercase())
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            && s.len() <= MAX_NAME_LEN
+        {
+            let mut name = [0; MAX_NAME_LEN];
+            name[..s.len()].copy_from_slice(s.as_bytes());
+            Ok(Self(name))
+        } else {
+            Err(ParseError::InvalidRegister {
+                name: s.to_string(),
+                line: None,
+            })
+        }
+    }
+}
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::fmt::Debug for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Reg({})", self.name())
+    }
+}
+
+/// Parses one instruction per line, rejecting opcodes outside of `dialect`.
+///
+/// # Errors
+///
+/// Returns an error for the first line that is not a valid instruction of the dialect.
+pub fn parse(input: &str, dialect: Dialect) -> Result<Vec<Instruction>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let instruction: Instruction = line.parse().map_err(|error| match error {
+                ParseError::InvalidRegister { name, line: None } => ParseError::InvalidRegister {
+                    name,
+                    line: Some(index + 1),
+                },
+                error => error,
+            })?;
+            if dialect.supports(instruction) {
+                Ok(instruction)
+            } else {
+                Err(ParseError::UnsupportedInstruction(line.to_string()))
+            }
+        })
+        .collect()
+}
+
+/// The registers of a program: `a` to `d`, then any others in the order they are first used.
+/// The position of a register in the table is its index in [`Machine::registers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers(Vec<Reg>);
+
+impl Registers {
+    #[must_use]
+    pub fn of(program: &[Instruction]) -> Self {
+        let mut regs = vec![Reg::A, Reg::B, Reg::C, Reg::D];
+        for reg in program
+            .iter()
+            .flat_map(|instruction| instruction.registers())
+        {
+            if !regs.contains(&reg) {
+                regs.push(reg);
+            }
+        }
+        Self(regs)
+    }
+
+    /// The position of the register, if the program uses it.
+    #[must_use]
+    pub fn index(&self, reg: Reg) -> Option<usize> {
+        self.0.iter().position(|&known| known == reg)
+    }
+
+    /// The position of a register that the program uses.
+    fn slot(&self, reg: Reg) -> usize {
+        self.index(reg)
+            .unwrap_or_else(|| panic!("The program does not use register {reg}"))
+    }
+}
+
+impl Deref for Registers {
+    type Target = [Reg];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine<'a> {
+    program: &'a [Instruction],
+    /// Borrows `program` until a `tgl` modifies it.
+    instructions: Cow<'a, [Instruction]>,
+    ip: usize,
+    regs: Registers,
+    /// Indexed by position in `regs`.
+    registers: Vec<i64>,
+    stopped: bool,
+    /// Whether the last step was blocked by an `in` with no input available.
+    waiting: bool,
+    input: VecDeque<i64>,
+    output: Vec<i64>,
+    /// Values sent to `output`, including those taken since. The output limit counts these.
+    emitted: usize,
+    output_limit: Option<usize>,
+    sink: Sink,
+    /// Fused view of `instructions`, kept in sync by `tgl`.
+    optimized: Option<Vec<Op>>,
+    backend: Backend,
+    /// Bytecode for the compiled backend, kept in sync by `tgl` once built.
+    compiled: Option<compiler::Compiled>,
+    /// Executions per instruction, when profiling.
+    profile: Option<Vec<u64>>,
+    tracer: Option<Tracer>,
+    arithmetic: Arithmetic,
+    overflow: Option<Overflow>,
+}
+
+impl<'a> Machine<'a> {
+    #[must_use]
+    pub fn new(program: &'a [Instruction]) -> Self {
+        let regs = Registers::of(program);
+        Self {
+            program,
+            instructions: Cow::Borrowed(program),
+            ip: 0,
+            registers: vec![0; regs.len()],
+            regs,
+            stopped: program.is_empty(),
+            waiting: false,
+            input: VecDeque::new(),
+            output: Vec::new(),
+            emitted: 0,
+            output_limit: None,
+            sink: Sink::Buffer,
+            optimized: None,
+            backend: Backend::Interpreter,
+            compiled: None,
+            profile: None,
+            tracer: None,
+            arithmetic: Arithmetic::Checked,
+            overflow: None,
+        }
+    }
+
+    /// Select how [`Machine::run`] executes the program. Single steps are always interpreted.
+    #[must_use]
+    pub const fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Execute addition and multiplication loops as single steps.
+    #[must_use]
+    pub fn with_optimizer(mut self) -> Self {
+        self.optimized = Some(optimize(&self.instructions));
+        self.compiled = None;
+        self
+    }
+
+    /// Stop the machine once `limit` values have been output.
+    #[must_use]
+    pub const fn with_output_limit(mut self, limit: usize) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Restores the machine to its initial state, undoing any toggles.
+    pub fn reset(&mut self) {
+        self.instructions = Cow::Borrowed(self.program);
+        self.ip = 0;
+        self.registers.fill(0);
+        self.stopped = self.program.is_empty();
+        self.waiting = false;
+        self.overflow = None;
+        self.input.clear();
+        self.output.clear();
+        self.emitted = 0;
+        if let Some(ops) = &mut self.optimized {
+            *ops = optimize(self.program);
+        }
+        self.compiled = None;
+        if let Some(counts) = &mut self.profile {
+            counts.fill(0);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.steps = 0;
+        }
+    }
+
+    #[must_use]
+    pub fn output(&self) -> &[i64] {
+        &self.output
+    }
+
+    #[must_use]
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    #[must_use]
+    pub const fn ip(&self) -> usize {
+        self.ip
+    }
+
+    /// The register values, in the order of [`Machine::regs`].
+    #[must_use]
+    pub fn registers(&self) -> &[i64] {
+        &self.registers
+    }
+
+    /// Every register the program uses, `a` to `d` first.
+    pub fn regs(&self) -> impl Iterator<Item = Reg> + '_ {
+        self.regs.iter().copied()
+    }
+
+    #[must_use]
+    pub const fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    #[must_use]
+    pub fn get_value(&self, source: RegOrValue) -> i64 {
+        match source {
+            RegOrValue::Reg(reg) => self[reg],
+            RegOrValue::Value(v) => v,
+        }
+    }
+
+    /// Resolves an instruction index relative to the current one.
+    fn relative_ip(&self, offset: RegOrValue) -> Option<usize> {
+        let offset = isize::try_from(self.get_value(offset)).ok()?;
+        self.ip
+            .checked_add_signed(offset)
+            .filter(|&ip| ip < self.instructions.len())
+    }
+
+    pub fn step(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.waiting = false;
+        let ip = self.ip;
+        let toggled = match self.instructions[ip] {
+            Instruction::Tgl(distance) if self.tracer.is_some() => self.relative_ip(distance),
+            _ => None,
+        };
+        let op = self.execute();
+        // An `in` waiting for input has not executed yet
+        if !self.waiting {
+            if let Some(counts) = &mut self.profile {
+                counts[ip] += 1;
+            }
+            self.trace(ip, op, toggled);
+        }
+    }
+
+    /// Executes the instruction or fused loop at the instruction pointer, returning which one.
+    fn execute(&mut self) -> Op {
+        if let Some(op) = self.optimized.as_ref().map(|ops| ops[self.ip]) {
+            match op.execute(&self.regs, &mut self.registers, self.arithmetic) {
+                Ok(true) => {
+                    self.ip += op.span();
+                    self.stopped = self.ip >= self.instructions.len();
+                    return op;
+                }
+                Ok(false) => {}
+                Err(reg) => {
+                    self.overflowed(reg);
+                    return op;
+                }
+            }
+        }
+        let instruction = self.instructions[self.ip];
+        match instruction {
+            Instruction::Cpy(value, RegOrValue::Reg(reg)) => {
+                self[reg] = self.arithmetic.truncate(self.get_value(value));
+            }
+            Instruction::Inc(RegOrValue::Reg(reg)) | Instruction::Dec(RegOrValue::Reg(reg)) => {
+                let delta = if matches!(instruction, Instruction::Inc(_)) {
+                    1
+                } else {
+                    -1
+                };
+                if !self.add(reg, delta) {
+                    return Op::Plain(instruction);
+                }
+            }
+            Instruction::Jnz(condition, distance) => {
+                if self.get_value(condition) != 0 {
+                    if let Some(new_ip) = self.relative_ip(distance) {
+                        self.ip = new_ip;
+                    } else {
+                        self.stopped = true;
+                    }
+                    return Op::Plain(instruction);
+                }
+            }
+            Instruction::Tgl(distance) => {
+                if let Some(target) = self.relative_ip(distance) {
+                    let instructions = self.instructions.to_mut();
+                    instructions[target] = instructions[target].toggle();
+                    if let Some(ops) = &mut self.optimized {
+                        optimizer::invalidate(ops, instructions, target);
+                    }
+                    if let Some(compiled) = &mut self.compiled {
+                        let ops = self.optimized.as_deref();
+                        compiled.invalidate(instructions, &self.regs, ops, target);
+                    }
+                }
+            }
+            Instruction::Out(value) => {
+                if !self.emit(self.get_value(value)) {
+                    self.stopped = true;
+                    return Op::Plain(instruction);
+                }
+            }
+            Instruction::In(RegOrValue::Reg(reg)) => {
+                let Some(value) = self.input.pop_front() else {
+                    self.waiting = true;
+                    return Op::Plain(instruction);
+                };
+                self[reg] = self.arithmetic.truncate(value);
+            }
+            // Toggling can produce invalid instructions, which are skipped
+            Instruction::Cpy(..)
+            | Instruction::Inc(..)
+            | Instruction::Dec(..)
+            | Instruction::In(..) => {}
+        }
+        self.ip += 1;
+        self.stopped = self.ip >= self.instructions.len();
+        Op::Plain(instruction)
+    }
+
+    pub fn run(&mut self) {
+        self.run_for(usize::MAX);
+    }
+
+    /// Runs like [`Machine::run`], but gives up after `max_steps` steps. Returns whether the
+    /// machine stopped or is waiting for input.
+    pub fn run_for(&mut self, max_steps: usize) -> bool {
+        if self.stopped {
+            return true;
+        }
+        match self.backend {
+            Backend::Compiled if self.profile.is_none() && self.tracer.is_none() => {
+                self.run_compiled(max_steps);
+            }
+            Backend::Interpreter | Backend::Compiled => {
+                for _ in 0..max_steps {
+                    self.step();
+                    if self.stopped || self.waiting {
+                        break;
+                    }
+                }
+            }
+        }
+        self.stopped || self.waiting
+    }
+}
+
+impl Index<Reg> for Machine<'_> {
+    type Output = i64;
+
+    fn index(&self, index: Reg) -> &Self::Output {
+        &self.registers[self.regs.slot(index)]
+    }
+}
+
+impl IndexMut<Reg> for Machine<'_> {
+    fn index_mut(&mut self, index: Reg) -> &mut Self::Output {
+        &mut self.registers[self.regs.slot(index)]
+    }
+}
+
+impl Display for Machine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let registers = self
+            .regs
+            .iter()
+            .map(|&reg| format!("{}: {}", reg.name().to_uppercase(), self[reg]))
+            .collect::<Vec<_>>();
+        writeln!(f, "{}", registers.join(", "))?;
+        for (i, instr) in self.instructions.iter().enumerate() {
+            let active = if self.ip == i { '>' } else { ' ' };
+            writeln!(f, "{i:2}) {active} {instr}")?;
+        }
+        writeln!(f, "---")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dialect() {
+        assert!(parse("tgl a", Dialect::Toggle).is_ok());
+        assert!(matches!(
+            parse("tgl a", Dialect::Basic),
+            Err(ParseError::UnsupportedInstruction(_))
+        ));
+        assert!(matches!(
+            parse("out a", Dialect::Toggle),
+            Err(ParseError::UnsupportedInstruction(_))
+        ));
+        assert!(matches!(
+            parse("inc a\ninc A", Dialect::Basic),
+            Err(ParseError::InvalidRegister { name, line: Some(2) }) if name == "A"
+        ));
+    }
+
+    #[test]
+    fn test_named_registers() {
+        let instructions = parse(
+            "cpy 3 tmp\ncpy tmp counter_2\ninc sum\ndec counter_2\njnz counter_2 -2\ncpy sum a",
+            Dialect::Basic,
+        )
+        .unwrap();
+        assert_eq!(instructions[2].to_string(), "inc sum");
+        let mut machine = Machine::new(&instructions);
+        assert_eq!(machine.registers().len(), 7);
+        machine.run();
+        assert_eq!(machine[Reg::A], 3);
+        assert_eq!(machine["tmp".parse().unwrap()], 3);
+        assert_eq!(
+            "Tmp".parse::<Reg>().unwrap_err().to_string(),
+            "Invalid register name \"Tmp\""
+        );
+    }
+
+    #[test]
+    fn test_register_tables() {
+        let other = parse("inc zz_other_program", Dialect::Basic).unwrap();
+        let mine = parse("inc zz_mine\ncpy zz_mine b", Dialect::Basic).unwrap();
+        let names = Machine::new(&mine)
+            .regs()
+            .map(|reg| reg.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "d", "zz_mine"]);
+        assert_eq!(
+            Registers::of(&other).index("zz_mine".parse().unwrap()),
+            None
+        );
+        assert!("sixteen_is_the_limit".parse::<Reg>().is_err());
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let source = "cpy 41 a\ninc b\ndec c\njnz d -2\ntgl a\nout 1";
+        let text = source
+            .lines()
+            .map(|line| line.parse::<Instruction>().unwrap().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(text, source);
+    }
+
+    #[test]
+    fn test_jump_out_of_range_stops() {
+        let instructions = parse("inc a\njnz a -5\ninc a", Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions);
+        machine.run();
+        assert_eq!(machine[Reg::A], 1);
+    }
+
+    #[test]
+    fn test_reset_undoes_toggles() {
+        let instructions = parse("tgl 1\ninc a", Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions);
+        machine.run();
+        assert_eq!(machine[Reg::A], -1);
+        machine.reset();
+        assert_eq!(machine.instructions(), instructions);
+    }
+
+    #[test]
+    fn test_output_limit() {
+        let instructions = parse("out a\ninc a\njnz 1 -2", Dialect::Clock).unwrap();
+        let mut machine = Machine::new(&instructions).with_output_limit(3);
+        machine.run();
+        assert_eq!(machine.output(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_run_for() {
+        let instructions = parse("inc a\njnz 1 -1", Dialect::Basic).unwrap();
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let mut machine = Machine::new(&instructions).with_backend(backend);
+            assert!(!machine.run_for(10));
+            assert_eq!(machine[Reg::A], 5);
+        }
+        let instructions = parse("cpy 3 a\ndec a\njnz a -1", Dialect::Basic).unwrap();
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let mut machine = Machine::new(&instructions).with_backend(backend);
+            assert!(machine.run_for(7));
+            assert!(machine.is_stopped());
+        }
+    }
+}
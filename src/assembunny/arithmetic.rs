@@ -0,0 +1,186 @@
+use thiserror::Error;
+
+use super::{Machine, Reg};
+
+/// How a machine handles values that do not fit in its registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    /// Stop the machine, reporting an [`Overflow`].
+    #[default]
+    Checked,
+    /// Registers are two's complement numbers with this many bits, between 1 and 64. Copied
+    /// values wrap as well.
+    Wrapping(u32),
+    /// Values are clamped to the range of `i64`.
+    Saturating,
+}
+
+impl Arithmetic {
+    /// Adds `delta` to `value`, or returns `None` if checked arithmetic overflows.
+    #[must_use]
+    pub fn add(self, value: i64, delta: i128) -> Option<i64> {
+        // Only wrapping arithmetic has deltas large enough for this to wrap
+        let sum = i128::from(value).wrapping_add(delta);
+        match self {
+            Self::Checked => i64::try_from(sum).ok(),
+            Self::Wrapping(bits) => Some(wrap(sum, bits)),
+            #[allow(clippy::cast_possible_truncation, reason = "Clamped to i64 first")]
+            Self::Saturating => Some(sum.clamp(i64::MIN.into(), i64::MAX.into()) as i64),
+        }
+    }
+
+    /// The value as stored in a register.
+    #[must_use]
+    pub fn truncate(self, value: i64) -> i64 {
+        match self {
+            Self::Wrapping(bits) => wrap(value.into(), bits),
+            Self::Checked | Self::Saturating => value,
+        }
+    }
+
+    /// The number of times `step` is added to `counter` before it reaches zero, if it ever does.
+    /// When wrapping, the count is only known modulo `2^bits`.
+    pub(super) fn iterations(self, counter: i64, step: i64) -> Option<i128> {
+        match (self, step, counter.signum()) {
+            (Self::Wrapping(bits), -1 | 1, _) => {
+                Some((-i128::from(counter) * i128::from(step)).rem_euclid(1 << bits))
+            }
+            (_, -1, 1) => Some(counter.into()),
+            (_, 1, -1) => Some(-i128::from(counter)),
+            _ => None,
+        }
+    }
+}
+
+/// Sign-extends the lowest `bits` bits of `value`.
+#[allow(clippy::cast_possible_truncation, reason = "At most 64 bits remain")]
+const fn wrap(value: i128, bits: u32) -> i64 {
+    let shift = i128::BITS - bits;
+    ((value << shift) >> shift) as i64
+}
+
+/// A register left the range of `i64` under [`Arithmetic::Checked`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("Register {reg} overflowed at instruction {ip}")]
+pub struct Overflow {
+    /// The instruction, or the first instruction of the fused loop, that overflowed.
+    pub ip: usize,
+    pub reg: Reg,
+}
+
+impl Machine<'_> {
+    /// Select how arithmetic overflow is handled. The default is [`Arithmetic::Checked`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a wrapping width is not between 1 and 64 bits.
+    #[must_use]
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        if let Arithmetic::Wrapping(bits) = arithmetic {
+            assert!((1..=64).contains(&bits), "Invalid register width {bits}");
+        }
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    /// The overflow that stopped the machine, if any.
+    #[must_use]
+    pub const fn overflow(&self) -> Option<Overflow> {
+        self.overflow
+    }
+
+    /// Adds `delta` to a register, stopping the machine if it overflows.
+    pub(super) fn add(&mut self, reg: Reg, delta: i128) -> bool {
+        if let Some(value) = self.arithmetic.add(self[reg], delta) {
+            self[reg] = value;
+            true
+        } else {
+            self.overflowed(reg);
+            false
+        }
+    }
+
+    pub(super) const fn overflowed(&mut self, reg: Reg) {
+        self.overflow = Some(Overflow { ip: self.ip, reg });
+        self.stopped = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Backend, Dialect, Instruction, parse};
+
+    /// Runs the program with each backend, with and without the optimizer.
+    fn run(instructions: &[Instruction], arithmetic: Arithmetic, a: i64) -> Vec<Machine<'_>> {
+        [
+            Machine::new(instructions),
+            Machine::new(instructions).with_optimizer(),
+            Machine::new(instructions).with_backend(Backend::Compiled),
+        ]
+        .into_iter()
+        .map(|machine| {
+            let mut machine = machine.with_arithmetic(arithmetic);
+            machine[Reg::A] = a;
+            machine.run();
+            machine
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_checked() {
+        let instructions = parse("inc b\ninc a\ninc c", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Checked, i64::MAX) {
+            assert_eq!(machine.overflow(), Some(Overflow { ip: 1, reg: Reg::A }));
+            assert!(machine.is_stopped());
+            assert_eq!(machine.registers()[..3], [i64::MAX, 1, 0]);
+        }
+        let instructions = parse("cpy 2 b\ninc a\ndec b\njnz b -2", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Checked, i64::MAX - 1) {
+            assert_eq!(
+                machine.overflow().map(|overflow| overflow.reg),
+                Some(Reg::A)
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let instructions = parse("inc a\ncpy 300 b", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Wrapping(8), 127) {
+            assert_eq!(machine.overflow(), None);
+            assert_eq!(machine.registers()[..2], [-128, 44]);
+        }
+        // Counting up from 5 only reaches zero by wrapping around
+        let instructions = parse("cpy 5 b\ninc a\ninc b\njnz b -2", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Wrapping(8), 0) {
+            assert_eq!(machine.registers()[..2], [-5, 0]);
+        }
+        let instructions = parse("dec a", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Wrapping(64), i64::MIN) {
+            assert_eq!(machine[Reg::A], i64::MAX);
+        }
+    }
+
+    #[test]
+    fn test_saturating() {
+        let instructions = parse("inc a\ninc a\ndec b", Dialect::Basic).unwrap();
+        for machine in run(&instructions, Arithmetic::Saturating, i64::MAX - 1) {
+            assert_eq!(machine.registers()[..2], [i64::MAX, -1]);
+        }
+        // Far too many steps to run without the optimizer
+        let instructions = parse(
+            "cpy 3 d\ncpy c b\ninc a\ndec b\njnz b -2\ndec d\njnz d -5",
+            Dialect::Basic,
+        )
+        .unwrap();
+        let mut machine = Machine::new(&instructions)
+            .with_optimizer()
+            .with_arithmetic(Arithmetic::Saturating);
+        machine[Reg::A] = -5;
+        machine[Reg::C] = i64::MAX / 2;
+        machine.run();
+        assert_eq!(machine[Reg::A], i64::MAX);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use thiserror::Error;
+
+use super::{Dialect, Instruction, ParseError, RegOrValue};
+
+#[derive(Debug, Error)]
+pub enum AssembleErrorKind {
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error("Invalid directive {0:?}")]
+    InvalidDirective(String),
+    #[error("Invalid symbol name {0:?}")]
+    InvalidName(String),
+    #[error("Symbol {0:?} is already defined")]
+    DuplicateSymbol(String),
+    #[error("Unknown symbol {0:?}")]
+    UnknownSymbol(String),
+    #[error("Label {0:?} can only be used as a jump offset")]
+    MisplacedLabel(String),
+}
+
+#[derive(Debug, Error)]
+#[error("Line {line}: {kind}")]
+pub struct AssembleError {
+    /// One-based line number in the source.
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Symbol {
+    Const(i64),
+    /// Index of the instruction following the label.
+    Label(usize),
+}
+
+/// An instruction that has not had its operands resolved yet.
+struct Pending<'a> {
+    line: usize,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles a program with labels, comments and named constants.
+///
+/// ```text
+/// .const N 5      # constants can be used wherever a number is expected
+///     cpy N c
+/// loop: inc a     # labels mark the next instruction
+///     dec c
+///     jnz c loop  # and are resolved to relative jump offsets
+/// ```
+///
+/// Labels are accepted as the offset operand of `jnz` and `tgl`. Any other lowercase identifier
+/// that is not a symbol names a register.
+///
+/// # Errors
+///
+/// Returns an error for the first line that is not valid, or that uses an undefined symbol.
+pub fn assemble(source: &str, dialect: Dialect) -> Result<Vec<Instruction>, AssembleError> {
+    let mut symbols = HashMap::new();
+    let mut pending = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |kind| AssembleError {
+            line: line_number,
+            kind,
+        };
+        let mut define = |name: &str, symbol| match symbols.entry(name.to_string()) {
+            _ if !is_valid_name(name) => Err(error(AssembleErrorKind::InvalidName(name.into()))),
+            Entry::Occupied(_) => Err(error(AssembleErrorKind::DuplicateSymbol(name.into()))),
+            Entry::Vacant(entry) => {
+                entry.insert(symbol);
+                Ok(())
+            }
+        };
+        let mut code = line.split_once('#').map_or(line, |(code, _)| code).trim();
+        if let Some(directive) = code.strip_prefix('.') {
+            match *directive.split_whitespace().collect::<Vec<_>>() {
+                ["const", name, value] => {
+                    let value = value
+                        .parse()
+                        .map_err(|e| error(ParseError::from(e).into()))?;
+                    define(name, Symbol::Const(value))?;
+                }
+                _ => return Err(error(AssembleErrorKind::InvalidDirective(code.into()))),
+            }
+            continue;
+        }
+        while let Some((label, rest)) = code.split_once(':') {
+            define(label.trim(), Symbol::Label(pending.len()))?;
+            code = rest.trim();
+        }
+        let mut words = code.split_whitespace();
+        if let Some(mnemonic) = words.next() {
+            pending.push(Pending {
+                line: line_number,
+                mnemonic,
+                operands: words.collect(),
+            });
+        }
+    }
+
+    pending
+        .iter()
+        .enumerate()
+        .map(|(ip, instruction)| {
+            let error = |kind| AssembleError {
+                line: instruction.line,
+                kind,
+            };
+            let offset_operand = match instruction.mnemonic {
+                "jnz" => Some(1),
+                "tgl" => Some(0),
+                _ => None,
+            };
+            let operands = instruction
+                .operands
+                .iter()
+                .enumerate()
+                .map(|(position, &operand)| {
+                    let value = match symbols.get(operand) {
+                        Some(&Symbol::Const(value)) => RegOrValue::Value(value),
+                        Some(&Symbol::Label(target)) if offset_operand == Some(position) =>
+                        {
+                            #[allow(
+                                clippy::cast_possible_wrap,
+                                reason = "Programs are much shorter than i64::MAX"
+                            )]
+                            RegOrValue::Value(target as i64 - ip as i64)
+                        }
+                        Some(Symbol::Label(_)) => {
+                            return Err(AssembleErrorKind::MisplacedLabel(operand.into()));
+                        }
+                        None => match operand.parse::<RegOrValue>() {
+                            Ok(value) => value,
+                            Err(ParseError::InvalidRegister { .. }) => {
+                                return Err(AssembleErrorKind::UnknownSymbol(operand.into()));
+                            }
+                            Err(e) => return Err(e.into()),
+                        },
+                    };
+                    Ok(value.to_string())
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?;
+            let text = format!("{} {}", instruction.mnemonic, operands.join(" "));
+            let parsed: Instruction = text.parse().map_err(|e: ParseError| error(e.into()))?;
+            if dialect.supports(parsed) {
+                Ok(parsed)
+            } else {
+                Err(error(
+                    ParseError::UnsupportedInstruction(text.trim().to_string()).into(),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Symbol names are identifiers other than the four standard registers. Other register names
+/// are shadowed by symbols of the same name.
+fn is_valid_name(name: &str) -> bool {
+    !matches!(name, "a" | "b" | "c" | "d")
+        && name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Machine, Reg, parse};
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+            # a = A * B\n\
+            .const A 6\n\
+            .const B 7\n\
+            \n\
+                cpy A d\n\
+            outer:\n\
+                cpy B c\n\
+            inner: inc a  # a += 1\n\
+                dec c\n\
+                jnz c inner\n\
+                dec d\n\
+                jnz d outer\n\
+                jnz 1 end\n\
+                inc b\n\
+            end:\
+        ";
+        let instructions = assemble(source, Dialect::Basic).unwrap();
+        let expected = parse(
+            "cpy 6 d\ncpy 7 c\ninc a\ndec c\njnz c -2\ndec d\njnz d -5\njnz 1 2\ninc b",
+            Dialect::Basic,
+        )
+        .unwrap();
+        assert_eq!(instructions, expected);
+
+        let mut machine = Machine::new(&instructions);
+        machine.run();
+        assert_eq!(machine[Reg::A], 42);
+        assert_eq!(machine[Reg::B], 0);
+    }
+
+    #[test]
+    fn test_raw_programs_are_unchanged() {
+        let source = "cpy 41 a\ninc a\ninc a\ndec a\njnz a 2\ndec a\ntgl -3";
+        assert_eq!(
+            assemble(source, Dialect::Toggle).unwrap(),
+            parse(source, Dialect::Toggle).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source, Dialect::Toggle).unwrap_err();
+
+        let err = error("inc a\n\njnz a Missing");
+        assert_eq!(err.line, 3);
+        assert!(matches!(err.kind, AssembleErrorKind::UnknownSymbol(name) if name == "Missing"));
+
+        let err = error("top: inc a\ncpy top b");
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, AssembleErrorKind::MisplacedLabel(_)));
+
+        let err = error(".const x 1\nx: inc a");
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, AssembleErrorKind::DuplicateSymbol(_)));
+
+        let err = error("a: inc a");
+        assert!(matches!(err.kind, AssembleErrorKind::InvalidName(_)));
+
+        let err = error("inc a\n.define x 1");
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, AssembleErrorKind::InvalidDirective(_)));
+
+        let err = error("out a");
+        assert!(matches!(
+            err.kind,
+            AssembleErrorKind::Parse(ParseError::UnsupportedInstruction(_))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Line 1: Instruction \"out a\" is not supported by this dialect"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::Write;
+
+use super::{Instruction, RegOrValue};
+
+/// Where a taken `jnz` continues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Ip(usize),
+    /// The jump leaves the program, which halts the machine.
+    Exit,
+    /// The offset is read from a register.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jump {
+    pub condition: RegOrValue,
+    pub target: Target,
+}
+
+impl Jump {
+    #[must_use]
+    pub const fn is_unconditional(&self) -> bool {
+        matches!(self.condition, RegOrValue::Value(value) if value != 0)
+    }
+}
+
+/// The jump performed by the instruction at `ip`, unless it can never be taken.
+#[must_use]
+pub fn jump_at(instructions: &[Instruction], ip: usize) -> Option<Jump> {
+    let Instruction::Jnz(condition, offset) = instructions[ip] else {
+        return None;
+    };
+    if condition == RegOrValue::Value(0) {
+        return None;
+    }
+    let target = match offset {
+        RegOrValue::Reg(_) => Target::Unknown,
+        RegOrValue::Value(offset) => isize::try_from(offset)
+            .ok()
+            .and_then(|offset| ip.checked_add_signed(offset))
+            .filter(|&target| target < instructions.len())
+            .map_or(Target::Exit, Target::Ip),
+    };
+    Some(Jump { condition, target })
+}
+
+/// A maximal range of instructions that is only entered at `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Successor {
+    Block(usize),
+    Exit,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    FallThrough,
+    Taken,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Successor,
+    pub kind: EdgeKind,
+}
+
+/// The static control flow of a program, ignoring any changes by `tgl`.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+    edges: Vec<Edge>,
+}
+
+impl ControlFlowGraph {
+    #[must_use]
+    pub fn new(instructions: &[Instruction]) -> Self {
+        let mut is_leader = vec![false; instructions.len() + 1];
+        is_leader[0] = true;
+        for ip in 0..instructions.len() {
+            if let Some(jump) = jump_at(instructions, ip) {
+                is_leader[ip + 1] = true;
+                if let Target::Ip(target) = jump.target {
+                    is_leader[target] = true;
+                }
+            }
+        }
+        let starts = (0..instructions.len())
+            .filter(|&ip| is_leader[ip])
+            .collect::<Vec<_>>();
+        let blocks = starts
+            .iter()
+            .zip(starts.iter().skip(1).chain([&instructions.len()]))
+            .map(|(&start, &end)| BasicBlock { start, end })
+            .collect::<Vec<_>>();
+
+        let block_of = |ip: usize| starts.partition_point(|&start| start <= ip) - 1;
+        let mut edges = Vec::new();
+        for (from, block) in blocks.iter().enumerate() {
+            let jump = jump_at(instructions, block.end - 1);
+            if let Some(jump) = jump {
+                let to = match jump.target {
+                    Target::Ip(target) => Successor::Block(block_of(target)),
+                    Target::Exit => Successor::Exit,
+                    Target::Unknown => Successor::Unknown,
+                };
+                edges.push(Edge {
+                    from,
+                    to,
+                    kind: EdgeKind::Taken,
+                });
+            }
+            if !jump.is_some_and(|jump| jump.is_unconditional()) {
+                let to = if block.end < instructions.len() {
+                    Successor::Block(from + 1)
+                } else {
+                    Successor::Exit
+                };
+                edges.push(Edge {
+                    from,
+                    to,
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+        }
+        Self { blocks, edges }
+    }
+
+    #[must_use]
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    #[must_use]
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// The index of the block containing the instruction at `ip`.
+    #[must_use]
+    pub fn block_of(&self, ip: usize) -> usize {
+        self.blocks.partition_point(|block| block.start <= ip) - 1
+    }
+
+    /// The blocks with an edge into `block`.
+    pub fn predecessors(&self, block: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |edge| edge.to == Successor::Block(block))
+            .map(|edge| edge.from)
+    }
+
+    /// Renders the graph in Graphviz DOT format, with the instructions of each block as its label.
+    ///
+    /// Jumps that leave the program go to an `exit` node, and jumps with a register offset to an
+    /// `unknown` node.
+    #[must_use]
+    pub fn to_dot(&self, instructions: &[Instruction]) -> String {
+        let mut dot =
+            String::from("digraph program {\n    node [shape=box, fontname=monospace];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let _ = write!(dot, "    b{index} [label=\"");
+            for (ip, instruction) in (block.start..).zip(&instructions[block.start..block.end]) {
+                let _ = write!(dot, "{ip}: {instruction}\\l");
+            }
+            dot.push_str("\"];\n");
+        }
+        let successors = self.edges.iter().map(|edge| edge.to);
+        if successors.clone().any(|to| to == Successor::Exit) {
+            dot.push_str("    exit [shape=oval];\n");
+        }
+        if successors.clone().any(|to| to == Successor::Unknown) {
+            dot.push_str("    unknown [shape=oval, label=\"?\"];\n");
+        }
+        for edge in &self.edges {
+            let to = match edge.to {
+                Successor::Block(block) => format!("b{block}"),
+                Successor::Exit => "exit".to_string(),
+                Successor::Unknown => "unknown".to_string(),
+            };
+            let style = match (edge.kind, edge.to) {
+                (EdgeKind::FallThrough, _) => "",
+                (EdgeKind::Taken, Successor::Unknown) => " [style=dashed]",
+                (EdgeKind::Taken, _) => " [style=bold]",
+            };
+            let _ = writeln!(dot, "    b{} -> {to}{style};", edge.from);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, parse};
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let instructions = parse(
+            "\
+            cpy 3 a\n\
+            dec a\n\
+            jnz a -1\n\
+            jnz 1 2\n\
+            inc b\n\
+            jnz b c\
+            ",
+            Dialect::Basic,
+        )
+        .unwrap();
+        let cfg = ControlFlowGraph::new(&instructions);
+        let ranges = cfg
+            .blocks()
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0, 1), (1, 3), (3, 4), (4, 5), (5, 6)]);
+        let edges = cfg
+            .edges()
+            .iter()
+            .map(|edge| (edge.from, edge.to))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            [
+                (0, Successor::Block(1)),
+                (1, Successor::Block(1)),
+                (1, Successor::Block(2)),
+                (2, Successor::Block(4)),
+                (3, Successor::Block(4)),
+                (4, Successor::Unknown),
+                (4, Successor::Exit),
+            ]
+        );
+        assert_eq!(cfg.predecessors(4).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(cfg.block_of(2), 1);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let instructions = parse("cpy 2 a\ndec a\njnz a -1\njnz a b", Dialect::Basic).unwrap();
+        let dot = ControlFlowGraph::new(&instructions).to_dot(&instructions);
+        assert_eq!(
+            dot,
+            "\
+digraph program {
+    node [shape=box, fontname=monospace];
+    b0 [label=\"0: cpy 2 a\\l\"];
+    b1 [label=\"1: dec a\\l2: jnz a -1\\l\"];
+    b2 [label=\"3: jnz a b\\l\"];
+    exit [shape=oval];
+    unknown [shape=oval, label=\"?\"];
+    b0 -> b1;
+    b1 -> b1 [style=bold];
+    b1 -> b2;
+    b2 -> unknown [style=dashed];
+    b2 -> exit;
+}
+"
+        );
+    }
+}
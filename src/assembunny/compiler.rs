@@ -0,0 +1,369 @@
+use super::{Instruction, Machine, Op, RegOrValue, Registers, optimizer};
+
+/// How [`Machine::run`] executes the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Decode each instruction as it is executed.
+    #[default]
+    Interpreter,
+    /// Translate the program to bytecode with resolved jump targets before running it.
+    Compiled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Code {
+    CopyValue {
+        value: i64,
+        dst: usize,
+    },
+    CopyReg {
+        src: usize,
+        dst: usize,
+    },
+    Inc(usize),
+    Dec(usize),
+    /// Absolute jump. Targets outside the program halt the machine.
+    Jump(usize),
+    JumpIfNonZero {
+        reg: usize,
+        target: usize,
+    },
+    /// Jump with an offset read from a register.
+    JumpDynamic {
+        condition: RegOrValue,
+        offset: usize,
+    },
+    Out(RegOrValue),
+    Fused(Op),
+    Nop,
+    /// Let the interpreter execute the instruction, for `tgl` and `in`.
+    Interpret,
+}
+
+impl Code {
+    fn compile(
+        instructions: &[Instruction],
+        regs: &Registers,
+        ops: Option<&[Op]>,
+        ip: usize,
+    ) -> Self {
+        if let Some(op @ (Op::Add { .. } | Op::Mul { .. })) = ops.map(|ops| ops[ip]) {
+            return Self::Fused(op);
+        }
+        let resolve = |offset: i64| {
+            isize::try_from(offset)
+                .ok()
+                .and_then(|offset| ip.checked_add_signed(offset))
+                .filter(|&target| target < instructions.len())
+                .unwrap_or(instructions.len())
+        };
+        match instructions[ip] {
+            Instruction::Cpy(RegOrValue::Value(value), RegOrValue::Reg(dst)) => Self::CopyValue {
+                value,
+                dst: regs.slot(dst),
+            },
+            Instruction::Cpy(RegOrValue::Reg(src), RegOrValue::Reg(dst)) => Self::CopyReg {
+                src: regs.slot(src),
+                dst: regs.slot(dst),
+            },
+            Instruction::Inc(RegOrValue::Reg(reg)) => Self::Inc(regs.slot(reg)),
+            Instruction::Dec(RegOrValue::Reg(reg)) => Self::Dec(regs.slot(reg)),
+            Instruction::Jnz(RegOrValue::Value(value), RegOrValue::Value(offset)) if value != 0 => {
+                Self::Jump(resolve(offset))
+            }
+            Instruction::Jnz(RegOrValue::Reg(reg), RegOrValue::Value(offset)) => {
+                Self::JumpIfNonZero {
+                    reg: regs.slot(reg),
+                    target: resolve(offset),
+                }
+            }
+            Instruction::Jnz(condition, RegOrValue::Reg(offset))
+                if condition != RegOrValue::Value(0) =>
+            {
+                Self::JumpDynamic {
+                    condition,
+                    offset: regs.slot(offset),
+                }
+            }
+            Instruction::Out(value) => Self::Out(value),
+            Instruction::Tgl(_) | Instruction::In(_) => Self::Interpret,
+            // Invalid instructions created by `tgl` are skipped, as is `jnz 0`
+            Instruction::Jnz(..)
+            | Instruction::Cpy(..)
+            | Instruction::Inc(..)
+            | Instruction::Dec(..) => Self::Nop,
+        }
+    }
+}
+
+/// Bytecode for a program, which has to be updated when `tgl` modifies the program.
+#[derive(Debug, Clone)]
+pub(super) struct Compiled {
+    code: Vec<Code>,
+}
+
+impl Compiled {
+    fn new(instructions: &[Instruction], regs: &Registers, ops: Option<&[Op]>) -> Self {
+        let code = (0..instructions.len())
+            .map(|ip| Code::compile(instructions, regs, ops, ip))
+            .collect();
+        Self { code }
+    }
+
+    /// Recompiles every instruction that might depend on the one at `changed`.
+    pub(super) fn invalidate(
+        &mut self,
+        instructions: &[Instruction],
+        regs: &Registers,
+        ops: Option<&[Op]>,
+        changed: usize,
+    ) {
+        let first = changed.saturating_sub(optimizer::MAX_SPAN - 1);
+        for ip in first..=changed {
+            self.code[ip] = Code::compile(instructions, regs, ops, ip);
+        }
+    }
+}
+
+impl Machine<'_> {
+    /// Runs the bytecode, compiling it on the first call and keeping it for the next ones.
+    pub(super) fn run_compiled(&mut self, max_steps: usize) {
+        let regs = self.regs.clone();
+        let mut compiled = self
+            .compiled
+            .take()
+            .unwrap_or_else(|| Compiled::new(&self.instructions, &regs, self.optimized.as_deref()));
+        let len = compiled.code.len();
+        let mut ip = self.ip;
+        let mut registers = std::mem::take(&mut self.registers);
+        let read = |registers: &[i64], source: RegOrValue| match source {
+            RegOrValue::Reg(reg) => registers[regs.slot(reg)],
+            RegOrValue::Value(value) => value,
+        };
+        let arithmetic = self.arithmetic;
+        let mut steps = max_steps;
+        while !self.stopped && steps > 0 {
+            steps -= 1;
+            match compiled.code[ip] {
+                Code::CopyValue { value, dst } => registers[dst] = arithmetic.truncate(value),
+                Code::CopyReg { src, dst } => registers[dst] = registers[src],
+                code @ (Code::Inc(reg) | Code::Dec(reg)) => {
+                    let delta = if matches!(code, Code::Inc(_)) { 1 } else { -1 };
+                    if let Some(value) = arithmetic.add(registers[reg], delta) {
+                        registers[reg] = value;
+                    } else {
+                        // Let the interpreter report the overflow
+                        (ip, registers) = self.interpret(ip, registers, &mut compiled);
+                        continue;
+                    }
+                }
+                Code::Jump(target) => {
+                    if target == len {
+                        self.stopped = true;
+                    } else {
+                        ip = target;
+                    }
+                    continue;
+                }
+                Code::JumpIfNonZero { reg, target } => {
+                    if registers[reg] != 0 {
+                        if target == len {
+                            self.stopped = true;
+                        } else {
+                            ip = target;
+                        }
+                        continue;
+                    }
+                }
+                Code::JumpDynamic { condition, offset } => {
+                    if read(&registers, condition) != 0 {
+                        match isize::try_from(registers[offset])
+                            .ok()
+                            .and_then(|offset| ip.checked_add_signed(offset))
+                            .filter(|&target| target < len)
+                        {
+                            Some(target) => ip = target,
+                            None => self.stopped = true,
+                        }
+                        continue;
+                    }
+                }
+                Code::Out(source) => {
+                    if !self.emit(read(&registers, source)) {
+                        self.stopped = true;
+                        continue;
+                    }
+                }
+                Code::Fused(op) if op.execute(&regs, &mut registers, arithmetic) == Ok(true) => {
+                    ip += op.span();
+                    self.stopped = ip >= len;
+                    continue;
+                }
+                Code::Fused(_) | Code::Interpret => {
+                    (ip, registers) = self.interpret(ip, registers, &mut compiled);
+                    if self.waiting {
+                        break;
+                    }
+                    continue;
+                }
+                Code::Nop => {}
+            }
+            ip += 1;
+            self.stopped = ip >= len;
+        }
+        self.ip = ip;
+        self.registers = registers;
+        self.compiled = Some(compiled);
+    }
+
+    /// Executes one instruction with the interpreter, updating the bytecode if it changed the
+    /// program.
+    fn interpret(
+        &mut self,
+        ip: usize,
+        registers: Vec<i64>,
+        compiled: &mut Compiled,
+    ) -> (usize, Vec<i64>) {
+        self.ip = ip;
+        self.registers = registers;
+        let toggled = match self.instructions[ip] {
+            Instruction::Tgl(offset) => self.relative_ip(offset),
+            _ => None,
+        };
+        self.step();
+        if let Some(target) = toggled {
+            compiled.invalidate(
+                &self.instructions,
+                &self.regs,
+                self.optimized.as_deref(),
+                target,
+            );
+        }
+        (self.ip, std::mem::take(&mut self.registers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::assembunny::{Dialect, Reg, parse};
+
+    /// Fibonacci numbers, in the style of day 12.
+    const FIBONACCI: &str = "\
+        cpy 1 a\n\
+        cpy 1 b\n\
+        cpy 26 d\n\
+        jnz c 2\n\
+        jnz 1 5\n\
+        cpy 7 c\n\
+        inc d\n\
+        dec c\n\
+        jnz c -2\n\
+        cpy a c\n\
+        inc a\n\
+        dec b\n\
+        jnz b -2\n\
+        cpy c b\n\
+        dec d\n\
+        jnz d -6\n\
+        cpy 13 c\n\
+        cpy 14 d\n\
+        inc a\n\
+        dec d\n\
+        jnz d -2\n\
+        dec c\n\
+        jnz c -5\
+    ";
+
+    fn run(instructions: &[Instruction], backend: Backend, c: i64) -> Vec<i64> {
+        let mut machine = Machine::new(instructions).with_backend(backend);
+        machine[Reg::C] = c;
+        machine.run();
+        machine.registers().to_vec()
+    }
+
+    #[test]
+    fn test_compiled_matches_interpreter() {
+        let instructions = parse(FIBONACCI, Dialect::Basic).unwrap();
+        assert_eq!(
+            run(&instructions, Backend::Compiled, 0),
+            run(&instructions, Backend::Interpreter, 0)
+        );
+    }
+
+    const TOGGLES: &str = "\
+        cpy 2 a\n\
+        tgl a\n\
+        tgl a\n\
+        tgl a\n\
+        cpy 1 a\n\
+        dec a\n\
+        dec a\
+    ";
+
+    #[test]
+    fn test_compiled_toggles() {
+        let instructions = parse(TOGGLES, Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions).with_backend(Backend::Compiled);
+        machine.run();
+        assert_eq!(machine[Reg::A], 3);
+        assert_eq!(
+            machine.instructions()[3],
+            Instruction::Inc(RegOrValue::Reg(Reg::A))
+        );
+    }
+
+    #[test]
+    fn test_compiled_bytecode_is_kept() {
+        // Toggles by single steps update the bytecode from the earlier runs
+        let instructions = parse(TOGGLES, Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions).with_backend(Backend::Compiled);
+        while !machine.run_for(1) {
+            machine.step();
+        }
+        assert_eq!(machine[Reg::A], 3);
+
+        // Restoring the untoggled program throws the toggled bytecode away
+        let instructions = parse("tgl 1\ninc b", Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions).with_backend(Backend::Compiled);
+        let mut snapshot = machine.snapshot();
+        snapshot.ip = 1;
+        machine.run();
+        assert_eq!(machine[Reg::B], -1);
+        machine.restore(&snapshot).unwrap();
+        machine.run();
+        assert_eq!(machine[Reg::B], 1);
+    }
+
+    #[test]
+    fn test_compiled_output_limit() {
+        let instructions = parse("out a\ninc a\njnz 1 -2", Dialect::Clock).unwrap();
+        let mut machine = Machine::new(&instructions)
+            .with_backend(Backend::Compiled)
+            .with_output_limit(3);
+        machine.run();
+        assert_eq!(machine.output(), [0, 1, 2]);
+        assert_eq!(machine.ip(), 0);
+        assert!(machine.is_stopped());
+    }
+
+    /// Where `cargo aoc input` saves the puzzle input.
+    const DAY_12_INPUT: &str = "input/2016/day12.txt";
+
+    #[test]
+    #[ignore = "Benchmark on the puzzle input, run with --release"]
+    fn bench_day_12_part_2() {
+        let input = std::fs::read_to_string(DAY_12_INPUT)
+            .unwrap_or_else(|_| panic!("Download {DAY_12_INPUT} with `cargo aoc input -d 12`"));
+        let instructions = parse(input.trim_end(), Dialect::Basic).unwrap();
+        let mut results = Vec::new();
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let start = Instant::now();
+            results.push(run(&instructions, backend, 1));
+            println!("{backend:?}: {:?}", start.elapsed());
+        }
+        assert_eq!(results[0], results[1]);
+    }
+}
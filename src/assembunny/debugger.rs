@@ -0,0 +1,367 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use super::{Instruction, Machine, Op, Reg, Registers, optimizer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchCondition {
+    /// Stop whenever the register changes.
+    Changed,
+    /// Stop when the register becomes equal to the value.
+    Equals(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub reg: Reg,
+    pub condition: WatchCondition,
+}
+
+impl Watchpoint {
+    /// Registers the program does not use never change.
+    fn is_triggered(self, regs: &Registers, before: &[i64], after: &[i64]) -> bool {
+        let Some(index) = regs.index(self.reg) else {
+            return false;
+        };
+        let (old, new) = (before[index], after[index]);
+        match self.condition {
+            WatchCondition::Changed => old != new,
+            WatchCondition::Equals(value) => old != new && new == value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A single step completed without hitting anything.
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint(Watchpoint),
+    Halted,
+    /// The machine needs more input to continue.
+    Waiting,
+    /// There is no more history to step back through.
+    Start,
+}
+
+/// The state before a step, enough to undo it.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    ip: usize,
+    registers: Vec<i64>,
+    stopped: bool,
+    output_len: usize,
+    /// The index and previous value of an instruction changed by `tgl`.
+    toggled: Option<(usize, Instruction)>,
+    /// The value read by `in`.
+    input: Option<i64>,
+}
+
+/// Wraps a [`Machine`] with breakpoints, watchpoints and a history for stepping backwards.
+#[derive(Debug, Clone)]
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    /// The most recent steps, oldest first.
+    history: VecDeque<Snapshot>,
+    history_limit: usize,
+}
+
+impl<'a> Debugger<'a> {
+    /// The number of steps kept for stepping back, unless set with
+    /// [`Debugger::with_history_limit`].
+    pub const DEFAULT_HISTORY_LIMIT: usize = 100_000;
+
+    #[must_use]
+    pub const fn new(machine: Machine<'a>) -> Self {
+        Self {
+            machine,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            history: VecDeque::new(),
+            history_limit: Self::DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    /// Only keep the last `limit` steps for stepping back. Older steps are forgotten.
+    #[must_use]
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        let forgotten = self.history.len().saturating_sub(limit);
+        self.history.drain(..forgotten);
+        self
+    }
+
+    #[must_use]
+    pub const fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    /// Sets a register. This counts as a step, so stepping back restores the previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register is not in the register file of the machine.
+    pub fn set_register(&mut self, reg: Reg, value: i64) {
+        let snapshot = self.snapshot(None);
+        self.machine[reg] = value;
+        self.record(snapshot);
+    }
+
+    /// Queues a value to be read by `in`.
+    pub fn push_input(&mut self, value: i64) {
+        self.machine.push_input(value);
+    }
+
+    /// The number of steps that can be undone.
+    #[must_use]
+    pub fn steps(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn add_breakpoint(&mut self, ip: usize) {
+        self.breakpoints.insert(ip);
+    }
+
+    pub fn remove_breakpoint(&mut self, ip: usize) -> bool {
+        self.breakpoints.remove(&ip)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoints(&mut self, reg: Reg) {
+        self.watchpoints.retain(|watchpoint| watchpoint.reg != reg);
+    }
+
+    #[must_use]
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Executes one instruction, or one fused loop when the optimizer is enabled.
+    pub fn step(&mut self) -> StopReason {
+        if self.machine.stopped {
+            return StopReason::Halted;
+        }
+        let before = self.machine.registers.clone();
+        let toggled = self.toggle_target().map(|target| {
+            let instruction = self.machine.instructions[target];
+            (target, instruction)
+        });
+        let mut snapshot = self.snapshot(toggled);
+        let input_len = self.machine.input.len();
+        let next_input = self.machine.input.front().copied();
+        self.machine.step();
+        if self.machine.waiting {
+            return StopReason::Waiting;
+        }
+        if self.machine.input.len() < input_len {
+            snapshot.input = next_input;
+        }
+        self.record(snapshot);
+        self.check_stop(&before)
+    }
+
+    fn snapshot(&self, toggled: Option<(usize, Instruction)>) -> Snapshot {
+        Snapshot {
+            ip: self.machine.ip,
+            registers: self.machine.registers.clone(),
+            stopped: self.machine.stopped,
+            output_len: self.machine.output.len(),
+            toggled,
+            input: None,
+        }
+    }
+
+    /// Adds a step to the history, forgetting the oldest one if it is full.
+    fn record(&mut self, snapshot: Snapshot) {
+        if self.history_limit == 0 {
+            return;
+        }
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+        }
+        self.history.push_back(snapshot);
+    }
+
+    /// Undoes the most recent step.
+    pub fn step_back(&mut self) -> StopReason {
+        let Some(snapshot) = self.history.pop_back() else {
+            return StopReason::Start;
+        };
+        let after = std::mem::replace(&mut self.machine.registers, snapshot.registers);
+        self.machine.ip = snapshot.ip;
+        self.machine.stopped = snapshot.stopped;
+        self.machine.overflow = None;
+        self.machine.emitted -= self.machine.output.len() - snapshot.output_len;
+        self.machine.output.truncate(snapshot.output_len);
+        if let Some(value) = snapshot.input {
+            self.machine.input.push_front(value);
+        }
+        if let Some((target, instruction)) = snapshot.toggled {
+            let instructions = self.machine.instructions.to_mut();
+            instructions[target] = instruction;
+            if let Some(ops) = &mut self.machine.optimized {
+                optimizer::invalidate(ops, instructions, target);
+            }
+            if let Some(compiled) = &mut self.machine.compiled {
+                let ops = self.machine.optimized.as_deref();
+                compiled.invalidate(instructions, &self.machine.regs, ops, target);
+            }
+        }
+        self.check_stop(&after)
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or the machine stops.
+    pub fn resume(&mut self) -> StopReason {
+        loop {
+            match self.step() {
+                StopReason::Stepped => {}
+                reason => return reason,
+            }
+        }
+    }
+
+    /// Steps backwards until a breakpoint or watchpoint is hit, or the history runs out.
+    pub fn reverse(&mut self) -> StopReason {
+        loop {
+            match self.step_back() {
+                StopReason::Stepped => {}
+                reason => return reason,
+            }
+        }
+    }
+
+    /// The instruction that will be modified if the next step is a `tgl`.
+    fn toggle_target(&self) -> Option<usize> {
+        let machine = &self.machine;
+        let is_fused = machine
+            .optimized
+            .as_ref()
+            .is_some_and(|ops| !matches!(ops[machine.ip], Op::Plain(_)));
+        match machine.instructions[machine.ip] {
+            Instruction::Tgl(offset) if !is_fused => machine.relative_ip(offset),
+            _ => None,
+        }
+    }
+
+    fn check_stop(&self, before: &[i64]) -> StopReason {
+        let after = &self.machine.registers;
+        if let Some(&watchpoint) = self
+            .watchpoints
+            .iter()
+            .find(|watchpoint| watchpoint.is_triggered(&self.machine.regs, before, after))
+        {
+            StopReason::Watchpoint(watchpoint)
+        } else if self.machine.stopped {
+            StopReason::Halted
+        } else if self.breakpoints.contains(&self.machine.ip) {
+            StopReason::Breakpoint(self.machine.ip)
+        } else {
+            StopReason::Stepped
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, parse};
+
+    const PROGRAM: &str = "\
+        cpy 2 a\n\
+        tgl a\n\
+        tgl a\n\
+        tgl a\n\
+        cpy 1 a\n\
+        dec a\n\
+        dec a\
+    ";
+
+    #[test]
+    fn test_breakpoints_and_watchpoints() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut debugger = Debugger::new(Machine::new(&instructions));
+        debugger.add_breakpoint(2);
+        assert_eq!(debugger.resume(), StopReason::Breakpoint(2));
+        assert!(debugger.remove_breakpoint(2));
+        let watchpoint = Watchpoint {
+            reg: Reg::A,
+            condition: WatchCondition::Equals(3),
+        };
+        debugger.add_watchpoint(watchpoint);
+        assert_eq!(debugger.resume(), StopReason::Watchpoint(watchpoint));
+        assert_eq!(debugger.machine().ip(), 4);
+        // The `cpy 1 a` was toggled into a `jnz`
+        assert_eq!(debugger.machine().instructions()[4].to_string(), "jnz 1 a");
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        assert_eq!(debugger.machine()[Reg::A], 3);
+    }
+
+    #[test]
+    fn test_step_back_undoes_toggles() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut debugger = Debugger::new(Machine::new(&instructions).with_optimizer());
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        let steps = debugger.steps();
+        debugger.add_breakpoint(2);
+        assert_eq!(debugger.reverse(), StopReason::Breakpoint(2));
+        assert_eq!(debugger.machine()[Reg::A], 2);
+        assert_eq!(debugger.reverse(), StopReason::Start);
+        assert_eq!(debugger.machine().instructions(), instructions);
+        assert_eq!(debugger.machine().registers(), [0; 4]);
+
+        debugger.remove_breakpoint(2);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        assert_eq!(debugger.steps(), steps);
+        assert_eq!(debugger.machine()[Reg::A], 3);
+    }
+
+    #[test]
+    fn test_input() {
+        let instructions = parse("in a\nin b", Dialect::Io).unwrap();
+        let mut debugger = Debugger::new(Machine::new(&instructions).with_input([3]));
+        assert_eq!(debugger.resume(), StopReason::Waiting);
+        assert_eq!(debugger.steps(), 1);
+        assert_eq!(debugger.step_back(), StopReason::Stepped);
+        assert_eq!(debugger.machine()[Reg::A], 0);
+        debugger.push_input(5);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        assert_eq!(debugger.machine().registers(), [3, 5, 0, 0]);
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut debugger = Debugger::new(Machine::new(&instructions)).with_history_limit(2);
+        assert_eq!(debugger.resume(), StopReason::Halted);
+        assert_eq!(debugger.steps(), 2);
+        assert_eq!(debugger.reverse(), StopReason::Start);
+        assert_eq!(debugger.machine().ip(), 3);
+        assert_eq!(debugger.machine()[Reg::A], 2);
+
+        let mut debugger = Debugger::new(Machine::new(&instructions)).with_history_limit(0);
+        assert_eq!(debugger.step(), StopReason::Stepped);
+        assert_eq!(debugger.step_back(), StopReason::Start);
+    }
+
+    #[test]
+    fn test_step_back_undoes_set_register() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut debugger = Debugger::new(Machine::new(&instructions));
+        debugger.step();
+        debugger.set_register(Reg::A, 7);
+        assert_eq!(debugger.steps(), 2);
+        assert_eq!(debugger.step_back(), StopReason::Stepped);
+        assert_eq!(debugger.machine()[Reg::A], 2);
+        assert_eq!(debugger.machine().ip(), 1);
+        assert_eq!(debugger.step_back(), StopReason::Stepped);
+        assert_eq!(debugger.machine()[Reg::A], 0);
+    }
+}
@@ -0,0 +1,691 @@
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter, Result};
+
+use super::cfg::{ControlFlowGraph, Jump, Target, jump_at};
+use super::{Instruction, Op, RegOrValue, optimize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cond {
+    Always,
+    NonZero(RegOrValue),
+    Zero(RegOrValue),
+}
+
+impl Cond {
+    const fn of(jump: Jump) -> Self {
+        if jump.is_unconditional() {
+            Self::Always
+        } else {
+            Self::NonZero(jump.condition)
+        }
+    }
+
+    const fn negate(self) -> Self {
+        match self {
+            Self::Always => Self::Always,
+            Self::NonZero(value) => Self::Zero(value),
+            Self::Zero(value) => Self::NonZero(value),
+        }
+    }
+}
+
+impl Display for Cond {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Self::Always => write!(f, "true"),
+            Self::NonZero(value) => write!(f, "{value} != 0"),
+            Self::Zero(value) => write!(f, "{value} == 0"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stmt {
+    Op(usize, Op),
+    /// `do { body } while cond`, or `loop { body }` if the condition is always true.
+    Loop {
+        head: usize,
+        cond: Cond,
+        body: Vec<Self>,
+    },
+    While {
+        head: usize,
+        cond: Cond,
+        body: Vec<Self>,
+    },
+    If {
+        ip: usize,
+        cond: Cond,
+        body: Vec<Self>,
+        otherwise: Vec<Self>,
+    },
+    Break {
+        ip: usize,
+        cond: Cond,
+        head: usize,
+    },
+    Continue {
+        ip: usize,
+        cond: Cond,
+        head: usize,
+    },
+    Goto {
+        ip: usize,
+        cond: Cond,
+        target: Target,
+        offset: RegOrValue,
+    },
+}
+
+impl Stmt {
+    const fn ip(&self) -> usize {
+        match *self {
+            Self::Op(ip, _)
+            | Self::If { ip, .. }
+            | Self::Break { ip, .. }
+            | Self::Continue { ip, .. }
+            | Self::Goto { ip, .. } => ip,
+            Self::Loop { head, .. } | Self::While { head, .. } => head,
+        }
+    }
+}
+
+/// Structured pseudocode recovered from an assembunny program.
+#[derive(Debug, Clone)]
+pub struct Pseudocode {
+    body: Vec<Stmt>,
+    /// Instructions targeted by a `goto`.
+    labels: HashSet<usize>,
+    /// Loops that are exited or continued from an inner loop.
+    named_loops: HashSet<usize>,
+}
+
+/// Recovers loops and conditionals from the jumps in the program.
+///
+/// Addition and multiplication loops are shown as single statements. Jumps that don't fit a
+/// structured construct are kept as `goto`.
+#[must_use]
+pub fn decompile(instructions: &[Instruction]) -> Pseudocode {
+    let mut decompiler = Decompiler {
+        instructions,
+        ops: optimize(instructions),
+        cfg: ControlFlowGraph::new(instructions),
+        loops: Vec::new(),
+        named_loops: HashSet::new(),
+    };
+    let body = decompiler.structure(0, instructions.len());
+    let mut labels = HashSet::new();
+    collect_labels(&body, &mut labels);
+    Pseudocode {
+        body,
+        labels,
+        named_loops: decompiler.named_loops,
+    }
+}
+
+fn collect_labels(body: &[Stmt], labels: &mut HashSet<usize>) {
+    for stmt in body {
+        match stmt {
+            Stmt::Goto {
+                target: Target::Ip(target),
+                ..
+            } => {
+                labels.insert(*target);
+            }
+            Stmt::Loop { body, .. } | Stmt::While { body, .. } => collect_labels(body, labels),
+            Stmt::If {
+                body, otherwise, ..
+            } => {
+                collect_labels(body, labels);
+                collect_labels(otherwise, labels);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Decompiler<'a> {
+    instructions: &'a [Instruction],
+    ops: Vec<Op>,
+    cfg: ControlFlowGraph,
+    /// `(head, exit)` of the enclosing loops, innermost last.
+    loops: Vec<(usize, usize)>,
+    named_loops: HashSet<usize>,
+}
+
+impl Decompiler<'_> {
+    fn structure(&mut self, lo: usize, hi: usize) -> Vec<Stmt> {
+        let mut body = Vec::new();
+        let mut ip = lo;
+        while ip < hi {
+            let (stmt, next) = self.statement(ip, hi);
+            body.push(stmt);
+            ip = next;
+        }
+        body
+    }
+
+    /// Whether the instructions in `lo + 1..hi` can only be reached from within `lo..hi`.
+    fn is_single_entry(&self, lo: usize, hi: usize) -> bool {
+        let blocks = self.cfg.blocks();
+        (self.cfg.block_of(lo) + 1..blocks.len())
+            .take_while(|&block| blocks[block].start < hi)
+            .all(|block| {
+                self.cfg.predecessors(block).all(|pred| {
+                    let source = blocks[pred].end - 1;
+                    (lo..hi).contains(&source)
+                })
+            })
+    }
+
+    fn statement(&mut self, ip: usize, hi: usize) -> (Stmt, usize) {
+        let loop_end = (ip..hi).rev().find(|&end| {
+            jump_at(self.instructions, end).is_some_and(|jump| jump.target == Target::Ip(ip))
+                && self.is_single_entry(ip, end + 1)
+        });
+        let op = self.ops[ip];
+        let fused = !matches!(op, Op::Plain(_))
+            && ip + op.span() <= hi
+            && self.is_single_entry(ip, ip + op.span());
+        if fused && loop_end.is_none_or(|end| end < ip + op.span()) {
+            return (Stmt::Op(ip, op), ip + op.span());
+        }
+        if let Some(end) = loop_end {
+            return (self.loop_statement(ip, end), end + 1);
+        }
+        let Some(jump) = jump_at(self.instructions, ip) else {
+            return (Stmt::Op(ip, Op::Plain(self.instructions[ip])), ip + 1);
+        };
+        let cond = Cond::of(jump);
+        if let Some(stmt) = self.loop_control(ip, cond, jump.target) {
+            return (stmt, ip + 1);
+        }
+        if let Target::Ip(target) = jump.target
+            && target > ip
+            && target <= hi
+            && !jump.is_unconditional()
+            && self.is_single_entry(ip, target)
+        {
+            // `jnz x 2; jnz 1 end` is the usual way to write `if x != 0`
+            if target == ip + 2
+                && let Some(skip) = jump_at(self.instructions, ip + 1)
+                && skip.is_unconditional()
+                && let Target::Ip(end) = skip.target
+                && end > target
+                && end <= hi
+                && self
+                    .loop_control(ip + 1, Cond::Always, skip.target)
+                    .is_none()
+                && self.is_single_entry(ip, end)
+            {
+                let body = self.structure(target, end);
+                let otherwise = Vec::new();
+                return (
+                    Stmt::If {
+                        ip,
+                        cond,
+                        body,
+                        otherwise,
+                    },
+                    end,
+                );
+            }
+            // A jump over the else branch at the end of the body
+            if let Some(skip) = jump_at(self.instructions, target - 1)
+                && skip.is_unconditional()
+                && let Target::Ip(end) = skip.target
+                && end > target
+                && end <= hi
+                && self
+                    .loop_control(target - 1, Cond::Always, skip.target)
+                    .is_none()
+                && self.is_single_entry(ip, end)
+            {
+                let body = self.structure(ip + 1, target - 1);
+                let otherwise = self.structure(target, end);
+                let cond = cond.negate();
+                return (
+                    Stmt::If {
+                        ip,
+                        cond,
+                        body,
+                        otherwise,
+                    },
+                    end,
+                );
+            }
+            let body = self.structure(ip + 1, target);
+            return (if_statement(ip, cond.negate(), body), target);
+        }
+        let Instruction::Jnz(_, offset) = self.instructions[ip] else {
+            unreachable!("Only jnz can jump");
+        };
+        let target = jump.target;
+        (
+            Stmt::Goto {
+                ip,
+                cond,
+                target,
+                offset,
+            },
+            ip + 1,
+        )
+    }
+
+    fn loop_statement(&mut self, head: usize, end: usize) -> Stmt {
+        self.loops.push((head, end + 1));
+        let mut body = self.structure(head, end);
+        self.loops.pop();
+        let cond = Cond::of(jump_at(self.instructions, end).unwrap());
+        if cond == Cond::Always
+            && let Some(&Stmt::Break {
+                cond: exit_cond,
+                head: exit_head,
+                ..
+            }) = body.first()
+            && exit_head == head
+            && exit_cond != Cond::Always
+        {
+            body.remove(0);
+            return Stmt::While {
+                head,
+                cond: exit_cond.negate(),
+                body,
+            };
+        }
+        Stmt::Loop { head, cond, body }
+    }
+
+    /// Translates jumps to the start or end of an enclosing loop.
+    fn loop_control(&mut self, ip: usize, cond: Cond, target: Target) -> Option<Stmt> {
+        let Target::Ip(target) = target else {
+            return None;
+        };
+        let depth = self
+            .loops
+            .iter()
+            .rposition(|&(head, exit)| target == head || target == exit)?;
+        let (head, exit) = self.loops[depth];
+        if depth + 1 < self.loops.len() {
+            self.named_loops.insert(head);
+        }
+        Some(if target == exit {
+            Stmt::Break { ip, cond, head }
+        } else {
+            Stmt::Continue { ip, cond, head }
+        })
+    }
+}
+
+/// Folds `if cond { break }` and similar into a conditional jump.
+fn if_statement(ip: usize, cond: Cond, mut body: Vec<Stmt>) -> Stmt {
+    if let [
+        Stmt::Break {
+            cond: inner @ Cond::Always,
+            ..
+        }
+        | Stmt::Continue {
+            cond: inner @ Cond::Always,
+            ..
+        }
+        | Stmt::Goto {
+            cond: inner @ Cond::Always,
+            ..
+        },
+    ] = body.as_mut_slice()
+    {
+        *inner = cond;
+        return body.pop().unwrap();
+    }
+    let otherwise = Vec::new();
+    Stmt::If {
+        ip,
+        cond,
+        body,
+        otherwise,
+    }
+}
+
+impl Pseudocode {
+    fn write_body(&self, f: &mut Formatter<'_>, body: &[Stmt], depth: usize) -> Result {
+        let indent = "    ".repeat(depth);
+        for stmt in body {
+            if self.labels.contains(&stmt.ip()) {
+                writeln!(f, "{indent}L{}:", stmt.ip())?;
+            }
+            match stmt {
+                Stmt::Op(ip, op) => write_op(f, &indent, *ip, *op)?,
+                Stmt::Loop { head, cond, body } => {
+                    let name = self.loop_name(*head);
+                    if *cond == Cond::Always {
+                        writeln!(f, "{indent}{name}loop {{")?;
+                        self.write_body(f, body, depth + 1)?;
+                        writeln!(f, "{indent}}}")?;
+                    } else {
+                        writeln!(f, "{indent}{name}do {{")?;
+                        self.write_body(f, body, depth + 1)?;
+                        writeln!(f, "{indent}}} while {cond}")?;
+                    }
+                }
+                Stmt::While { head, cond, body } => {
+                    let name = self.loop_name(*head);
+                    writeln!(f, "{indent}{name}while {cond} {{")?;
+                    self.write_body(f, body, depth + 1)?;
+                    writeln!(f, "{indent}}}")?;
+                }
+                Stmt::If {
+                    cond,
+                    body,
+                    otherwise,
+                    ..
+                } => {
+                    writeln!(f, "{indent}if {cond} {{")?;
+                    self.write_body(f, body, depth + 1)?;
+                    if !otherwise.is_empty() {
+                        writeln!(f, "{indent}}} else {{")?;
+                        self.write_body(f, otherwise, depth + 1)?;
+                    }
+                    writeln!(f, "{indent}}}")?;
+                }
+                Stmt::Break { cond, head, .. } => {
+                    let jump = format!("break{}", self.loop_reference(*head));
+                    write_jump(f, &indent, *cond, &jump)?;
+                }
+                Stmt::Continue { cond, head, .. } => {
+                    let jump = format!("continue{}", self.loop_reference(*head));
+                    write_jump(f, &indent, *cond, &jump)?;
+                }
+                Stmt::Goto {
+                    ip,
+                    cond,
+                    target,
+                    offset,
+                } => {
+                    let jump = match target {
+                        Target::Ip(target) => format!("goto L{target}"),
+                        Target::Exit => "halt".to_string(),
+                        Target::Unknown => format!("goto {ip} + {offset}"),
+                    };
+                    write_jump(f, &indent, *cond, &jump)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_name(&self, head: usize) -> String {
+        if self.named_loops.contains(&head) {
+            format!("'l{head}: ")
+        } else {
+            String::new()
+        }
+    }
+
+    fn loop_reference(&self, head: usize) -> String {
+        if self.named_loops.contains(&head) {
+            format!(" 'l{head}")
+        } else {
+            String::new()
+        }
+    }
+}
+
+fn write_jump(f: &mut Formatter<'_>, indent: &str, cond: Cond, jump: &str) -> Result {
+    if cond == Cond::Always {
+        writeln!(f, "{indent}{jump}")
+    } else {
+        writeln!(f, "{indent}if {cond} {{ {jump} }}")
+    }
+}
+
+/// Formats `target += factor * term`, hiding factors of one.
+fn write_add(
+    f: &mut Formatter<'_>,
+    indent: &str,
+    target: impl Display,
+    factor: i64,
+    term: &str,
+) -> Result {
+    let sign = if factor < 0 { '-' } else { '+' };
+    match factor.unsigned_abs() {
+        1 => writeln!(f, "{indent}{target} {sign}= {term}"),
+        factor => writeln!(f, "{indent}{target} {sign}= {factor} * {term}"),
+    }
+}
+
+fn write_op(f: &mut Formatter<'_>, indent: &str, ip: usize, op: Op) -> Result {
+    match op {
+        Op::Plain(instruction) => match instruction {
+            Instruction::Cpy(value, RegOrValue::Reg(reg)) => writeln!(f, "{indent}{reg} = {value}"),
+            Instruction::Inc(RegOrValue::Reg(reg)) => writeln!(f, "{indent}{reg} += 1"),
+            Instruction::Dec(RegOrValue::Reg(reg)) => writeln!(f, "{indent}{reg} -= 1"),
+            Instruction::Tgl(RegOrValue::Value(offset)) => {
+                #[expect(clippy::cast_possible_wrap, reason = "Programs are short")]
+                let target = ip as i64 + offset;
+                writeln!(f, "{indent}toggle({target})")
+            }
+            Instruction::Tgl(offset) => writeln!(f, "{indent}toggle({ip} + {offset})"),
+            Instruction::Out(value) => writeln!(f, "{indent}out({value})"),
+            Instruction::In(RegOrValue::Reg(reg)) => writeln!(f, "{indent}{reg} = in()"),
+            // Jumps that are never taken, and instructions invalidated by toggling
+            _ => writeln!(f, "{indent}nop  // {instruction}"),
+        },
+        Op::Add {
+            target,
+            target_step,
+            counter,
+            counter_step,
+        } => {
+            write_add(
+                f,
+                indent,
+                target,
+                -target_step * counter_step,
+                &counter.to_string(),
+            )?;
+            writeln!(f, "{indent}{counter} = 0")
+        }
+        Op::Mul {
+            source,
+            target,
+            target_step,
+            inner,
+            inner_step,
+            outer,
+            outer_step,
+        } => {
+            let factor = target_step * inner_step * outer_step;
+            write_add(f, indent, target, factor, &format!("{source} * {outer}"))?;
+            writeln!(f, "{indent}{inner} = 0")?;
+            writeln!(f, "{indent}{outer} = 0")
+        }
+    }
+}
+
+impl Display for Pseudocode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.write_body(f, &self.body, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, parse};
+
+    #[test]
+    fn test_if_and_do_while() {
+        let instructions = parse(
+            "\
+        cpy 1 a\n\
+        cpy 1 b\n\
+        cpy 26 d\n\
+        jnz c 2\n\
+        jnz 1 5\n\
+        cpy 7 c\n\
+        inc d\n\
+        dec c\n\
+        jnz c -2\n\
+        cpy a c\n\
+        inc a\n\
+        dec b\n\
+        jnz b -2\n\
+        cpy c b\n\
+        dec d\n\
+        jnz d -6\n\
+        cpy 13 c\n\
+        cpy 14 d\n\
+        inc a\n\
+        dec d\n\
+        jnz d -2\n\
+        dec c\n\
+        jnz c -5\
+            ",
+            Dialect::Basic,
+        )
+        .unwrap();
+        assert_eq!(
+            decompile(&instructions).to_string(),
+            "\
+            a = 1\n\
+            b = 1\n\
+            d = 26\n\
+            if c != 0 {\n\
+            \x20   c = 7\n\
+            \x20   d += c\n\
+            \x20   c = 0\n\
+            }\n\
+            do {\n\
+            \x20   c = a\n\
+            \x20   a += b\n\
+            \x20   b = 0\n\
+            \x20   b = c\n\
+            \x20   d -= 1\n\
+            } while d != 0\n\
+            c = 13\n\
+            a += 14 * c\n\
+            d = 0\n\
+            c = 0\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_nested_loops() {
+        let instructions = parse(
+            "\
+        cpy a d\n\
+        cpy 4 c\n\
+        cpy 633 b\n\
+        inc d\n\
+        dec b\n\
+        jnz b -2\n\
+        dec c\n\
+        jnz c -5\n\
+        cpy d a\n\
+        jnz 0 0\n\
+        cpy a b\n\
+        cpy 0 a\n\
+        cpy 2 c\n\
+        jnz b 2\n\
+        jnz 1 6\n\
+        dec b\n\
+        dec c\n\
+        jnz c -4\n\
+        inc a\n\
+        jnz 1 -7\n\
+        cpy 2 b\n\
+        jnz c 2\n\
+        jnz 1 4\n\
+        dec b\n\
+        dec c\n\
+        jnz 1 -4\n\
+        jnz 0 0\n\
+        out b\n\
+        jnz a -19\n\
+        jnz 1 -21\
+            ",
+            Dialect::Clock,
+        )
+        .unwrap();
+        assert_eq!(
+            decompile(&instructions).to_string(),
+            "\
+            d = a\n\
+            c = 4\n\
+            d += 633 * c\n\
+            b = 0\n\
+            c = 0\n\
+            loop {\n\
+            \x20   a = d\n\
+            \x20   do {\n\
+            \x20       nop  // jnz 0 0\n\
+            \x20       b = a\n\
+            \x20       a = 0\n\
+            \x20       'l12: loop {\n\
+            \x20           c = 2\n\
+            \x20           do {\n\
+            \x20               if b == 0 { break 'l12 }\n\
+            \x20               b -= 1\n\
+            \x20               c -= 1\n\
+            \x20           } while c != 0\n\
+            \x20           a += 1\n\
+            \x20       }\n\
+            \x20       b = 2\n\
+            \x20       while c != 0 {\n\
+            \x20           b -= 1\n\
+            \x20           c -= 1\n\
+            \x20       }\n\
+            \x20       nop  // jnz 0 0\n\
+            \x20       out(b)\n\
+            \x20   } while a != 0\n\
+            }\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_if_else() {
+        let instructions = parse(
+            "\
+            jnz a 3\n\
+            inc b\n\
+            jnz 1 2\n\
+            inc c\n\
+            tgl c\n\
+            jnz c d\
+            ",
+            Dialect::Toggle,
+        )
+        .unwrap();
+        assert_eq!(
+            decompile(&instructions).to_string(),
+            "\
+            if a == 0 {\n\
+            \x20   b += 1\n\
+            } else {\n\
+            \x20   c += 1\n\
+            }\n\
+            toggle(4 + c)\n\
+            if c != 0 { goto 5 + d }\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_goto() {
+        // Jumping into the middle of the loop prevents structuring it
+        let instructions = parse("jnz a 2\ninc b\ndec a\njnz a -2", Dialect::Basic).unwrap();
+        assert_eq!(
+            decompile(&instructions).to_string(),
+            "\
+            if a != 0 { goto L2 }\n\
+            L1:\n\
+            b += 1\n\
+            L2:\n\
+            a -= 1\n\
+            if a != 0 { goto L1 }\n\
+            "
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Differential testing of the ways to run a program. Random programs are run by each strategy,
+//! and any disagreement is shrunk to a minimal program before it is reported.
+
+use super::signal::{BITS_PRINTER, BITS_PROGRAM, bits_offset, solve_bits};
+use super::{
+    Backend, Dialect, Expr, Instruction, Machine, Reg, RegOrValue, RunOutcome, Snapshot,
+    SymbolicMachine, parse,
+};
+
+/// Programs that run for longer than this are not compared, and no strategy runs for longer.
+const MAX_STEPS: usize = 10_000;
+
+/// Random programs to try.
+const CASES: usize = 10_000;
+
+/// Xorshift generator, so failures can be reproduced from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n`.
+    #[allow(clippy::cast_possible_truncation, reason = "Less than n")]
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// A number in `-max..=max`.
+    #[allow(clippy::cast_possible_wrap, reason = "Small numbers")]
+    fn value(&mut self, max: usize) -> i64 {
+        self.below(2 * max + 1) as i64 - max as i64
+    }
+
+    fn reg(&mut self) -> Reg {
+        [Reg::A, Reg::B, Reg::C, Reg::D][self.below(4)]
+    }
+
+    fn operand(&mut self, max: usize) -> RegOrValue {
+        if self.below(3) == 0 {
+            RegOrValue::Value(self.value(max))
+        } else {
+            RegOrValue::Reg(self.reg())
+        }
+    }
+
+    fn instruction(&mut self) -> Instruction {
+        let reg = RegOrValue::Reg(self.reg());
+        match self.below(14) {
+            0..=2 => Instruction::Cpy(self.operand(5), reg),
+            3..=5 => Instruction::Inc(reg),
+            6..=8 => Instruction::Dec(reg),
+            9..=11 => Instruction::Jnz(self.operand(2), self.operand(4)),
+            12 => Instruction::Tgl(self.operand(4)),
+            _ if self.below(2) == 0 => Instruction::Out(self.operand(2)),
+            _ => Instruction::In(reg),
+        }
+    }
+}
+
+/// A program with its initial registers and input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Case {
+    program: Vec<Instruction>,
+    registers: [i64; 4],
+    input: Vec<i64>,
+    /// For programs that never stop on their own.
+    output_limit: Option<usize>,
+}
+
+impl Case {
+    fn random(rng: &mut Rng) -> Self {
+        let len = 1 + rng.below(12);
+        Self {
+            program: (0..len).map(|_| rng.instruction()).collect(),
+            registers: [(); 4].map(|()| rng.value(5)),
+            input: (0..rng.below(4)).map(|_| rng.value(5)).collect(),
+            output_limit: None,
+        }
+    }
+
+    /// A random preamble followed by the printer from [`BITS_PROGRAM`], which prints the bits of
+    /// `d` forever.
+    fn printer(rng: &mut Rng) -> Self {
+        let template = parse(BITS_PROGRAM, Dialect::Clock).unwrap();
+        let (copy, printer) = template.split_at(BITS_PRINTER - 1);
+        let preamble = (0..rng.below(6)).map(|_| rng.instruction());
+        Self {
+            program: copy[..1]
+                .iter()
+                .copied()
+                .chain(preamble)
+                .chain(printer.iter().copied())
+                .collect(),
+            registers: [(); 4].map(|()| rng.value(5)),
+            input: Vec::new(),
+            output_limit: Some(8 + rng.below(16)),
+        }
+    }
+
+    /// The program, without the initial registers and input.
+    fn blank(&self) -> Machine<'_> {
+        let machine = Machine::new(&self.program);
+        match self.output_limit {
+            Some(limit) => machine.with_output_limit(limit),
+            None => machine,
+        }
+    }
+
+    fn machine(&self) -> Machine<'_> {
+        let mut machine = self.blank().with_input(self.input.iter().copied());
+        machine.registers.copy_from_slice(&self.registers);
+        machine
+    }
+
+    /// Simpler versions of the case, simplest first.
+    fn shrink(&self) -> Vec<Self> {
+        let mut candidates = Vec::new();
+        for ip in 0..self.program.len() {
+            let mut case = self.clone();
+            case.program.remove(ip);
+            candidates.push(case);
+        }
+        for i in 0..self.input.len() {
+            let mut case = self.clone();
+            case.input.remove(i);
+            candidates.push(case);
+        }
+        for ip in 0..self.program.len() {
+            for instruction in simplify(self.program[ip]) {
+                let mut case = self.clone();
+                case.program[ip] = instruction;
+                candidates.push(case);
+            }
+        }
+        for i in 0..self.registers.len() {
+            for value in towards_zero(self.registers[i]) {
+                let mut case = self.clone();
+                case.registers[i] = value;
+                candidates.push(case);
+            }
+        }
+        for i in 0..self.input.len() {
+            for value in towards_zero(self.input[i]) {
+                let mut case = self.clone();
+                case.input[i] = value;
+                candidates.push(case);
+            }
+        }
+        candidates
+    }
+}
+
+/// Smaller values to try in place of `value`.
+fn towards_zero(value: i64) -> Vec<i64> {
+    let mut values = vec![0, value / 2, value - value.signum()];
+    values.dedup();
+    values.retain(|&smaller| smaller != value);
+    values
+}
+
+/// The instruction with one of its constants moved towards zero.
+fn simplify(instruction: Instruction) -> Vec<Instruction> {
+    let smaller = |operand| match operand {
+        RegOrValue::Value(value) => towards_zero(value)
+            .into_iter()
+            .map(RegOrValue::Value)
+            .collect(),
+        RegOrValue::Reg(_) => Vec::new(),
+    };
+    match instruction {
+        Instruction::Cpy(a, b) => smaller(a)
+            .into_iter()
+            .map(|a| Instruction::Cpy(a, b))
+            .collect(),
+        Instruction::Jnz(a, b) => smaller(a)
+            .into_iter()
+            .map(|a| Instruction::Jnz(a, b))
+            .chain(smaller(b).into_iter().map(|b| Instruction::Jnz(a, b)))
+            .collect(),
+        Instruction::Tgl(a) => smaller(a).into_iter().map(Instruction::Tgl).collect(),
+        Instruction::Out(a) => smaller(a).into_iter().map(Instruction::Out).collect(),
+        Instruction::Inc(_) | Instruction::Dec(_) | Instruction::In(_) => Vec::new(),
+    }
+}
+
+/// What a strategy computed. Strategies that can not see some part of the state leave it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Outcome {
+    registers: Option<Vec<i64>>,
+    output: Vec<i64>,
+    instructions: Option<Vec<Instruction>>,
+    /// Where a machine is waiting for input. The instruction pointer of a stopped machine
+    /// depends on how it jumped out of the program.
+    waiting_at: Option<usize>,
+    /// Whether the machine stopped or ran out of input within [`MAX_STEPS`].
+    finished: bool,
+}
+
+impl Outcome {
+    fn of(machine: &Machine) -> Self {
+        Self {
+            registers: Some(machine.registers().to_vec()),
+            output: machine.output().to_vec(),
+            instructions: Some(machine.instructions().to_vec()),
+            waiting_at: machine.is_waiting().then_some(machine.ip()),
+            finished: machine.is_stopped() || machine.is_waiting(),
+        }
+    }
+
+    fn agrees_with(&self, reference: &Self) -> bool {
+        self.finished == reference.finished
+            && (self.registers.is_none() || self.registers == reference.registers)
+            && self.output == reference.output
+            && (self.instructions.is_none()
+                || (self.instructions == reference.instructions
+                    && self.waiting_at == reference.waiting_at))
+    }
+}
+
+/// A way to run a case, or `None` if the strategy does not apply.
+type Strategy = fn(&Case) -> Option<Outcome>;
+
+/// Plain interpretation, one instruction per step. Every other strategy is compared to this.
+fn interpret(case: &Case) -> Option<Outcome> {
+    let mut machine = case.machine();
+    match machine.run_with_limit(MAX_STEPS) {
+        RunOutcome::Halted { .. } | RunOutcome::Waiting { .. } => Some(Outcome::of(&machine)),
+        RunOutcome::LimitReached | RunOutcome::Looping { .. } => None,
+    }
+}
+
+fn run(machine: Machine) -> Outcome {
+    let mut machine = machine;
+    machine.run_for(MAX_STEPS);
+    Outcome::of(&machine)
+}
+
+/// The shortcut from day 25: a program ending with the printer repeats the bits of `a` plus a
+/// constant, and the smallest `a` that prints the same bits has to print the same output.
+fn printer_shortcut(case: &Case) -> Option<Outcome> {
+    let limit = case.output_limit?;
+    let offset = bits_offset(&case.program)?;
+    let printed =
+        |value: i64| (0..(i64::BITS - value.leading_zeros()).max(1)).map(move |i| (value >> i) & 1);
+    let value = case.machine()[Reg::A]
+        .checked_add(offset)
+        .filter(|&value| value >= 0)?;
+    let solved = solve_bits(offset, &printed(value).collect::<Vec<_>>())?;
+    Some(Outcome {
+        registers: None,
+        output: printed(solved + offset).cycle().take(limit).collect(),
+        instructions: None,
+        waiting_at: None,
+        finished: true,
+    })
+}
+
+const STRATEGIES: [(&str, Strategy); 6] = [
+    ("optimizer", |case| {
+        Some(run(case.machine().with_optimizer()))
+    }),
+    ("compiled", |case| {
+        Some(run(case.machine().with_backend(Backend::Compiled)))
+    }),
+    ("compiled with optimizer", |case| {
+        Some(run(case
+            .machine()
+            .with_optimizer()
+            .with_backend(Backend::Compiled)))
+    }),
+    ("snapshot", |case| {
+        let mut machine = case.machine();
+        for _ in 0..case.program.len() {
+            machine.step();
+        }
+        let snapshot = Snapshot::from_json(&machine.snapshot().to_json()).ok()?;
+        let mut restored = case.blank().with_optimizer();
+        restored.restore(&snapshot).ok()?;
+        Some(run(restored))
+    }),
+    ("symbolic", |case| {
+        // Runs to the end, past any output limit
+        if case.output_limit.is_some() {
+            return None;
+        }
+        let mut machine = SymbolicMachine::new(&case.program);
+        machine.run().ok()?;
+        let initial = case.machine();
+        let evaluate = |exprs: &[Expr]| {
+            exprs
+                .iter()
+                .map(|expr| expr.evaluate(|reg| initial[reg]))
+                .collect::<Option<Vec<_>>>()
+        };
+        Some(Outcome {
+            registers: Some(evaluate(machine.registers())?),
+            output: evaluate(machine.outputs())?,
+            instructions: None,
+            waiting_at: None,
+            finished: true,
+        })
+    }),
+    ("printer shortcut", printer_shortcut),
+];
+
+/// Describes the first strategy that disagrees with plain interpretation.
+fn check(case: &Case) -> Result<(), String> {
+    let Some(reference) = interpret(case) else {
+        return Ok(());
+    };
+    for (name, strategy) in STRATEGIES {
+        if let Some(outcome) = strategy(case)
+            && !outcome.agrees_with(&reference)
+        {
+            return Err(format!(
+                "{name} gave {outcome:?}, but the interpreter gave {reference:?}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Shrinks a case for as long as it keeps failing.
+fn minimize(case: Case, fails: impl Fn(&Case) -> bool) -> Case {
+    let mut case = case;
+    while let Some(smaller) = case.shrink().into_iter().find(&fails) {
+        case = smaller;
+    }
+    case
+}
+
+mod tests {
+    use super::*;
+
+    fn assert_agree(case: Case) {
+        if check(&case).is_err() {
+            let case = minimize(case, |case| check(case).is_err());
+            let program = case
+                .program
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n");
+            panic!(
+                "{}\nregisters: {:?}, input: {:?}, output limit: {:?}\n{program}",
+                check(&case).unwrap_err(),
+                case.registers,
+                case.input,
+                case.output_limit,
+            );
+        }
+    }
+
+    #[test]
+    fn test_strategies_agree() {
+        let mut rng = Rng(0x2016_1225);
+        for _ in 0..CASES {
+            assert_agree(Case::random(&mut rng));
+        }
+    }
+
+    #[test]
+    fn test_printer_strategies_agree() {
+        let mut rng = Rng(0x2016_1225);
+        let mut shortcuts = 0;
+        for _ in 0..CASES / 10 {
+            let case = Case::printer(&mut rng);
+            shortcuts += usize::from(printer_shortcut(&case).is_some());
+            assert_agree(case);
+        }
+        assert!(
+            shortcuts > CASES / 100,
+            "Only {shortcuts} cases used the shortcut"
+        );
+    }
+
+    #[test]
+    fn test_minimize() {
+        let mut rng = Rng(1);
+        let case = std::iter::repeat_with(|| Case::random(&mut rng))
+            .find(|case| case.program.len() > 5 && outputs_value(case))
+            .unwrap();
+        let case = minimize(case, outputs_value);
+        assert!(matches!(case.program[..], [Instruction::Out(_)]));
+        assert_eq!(case.registers, [0; 4]);
+        assert!(case.input.is_empty());
+    }
+
+    fn outputs_value(case: &Case) -> bool {
+        interpret(case).is_some_and(|outcome| !outcome.output.is_empty())
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt::{Debug, Formatter};
+use std::ops::{Index, IndexMut};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use super::Machine;
+
+/// Where `out` sends its values.
+#[derive(Clone, Default)]
+pub enum Sink {
+    /// Collect the values in [`Machine::output`], up to the output limit.
+    #[default]
+    Buffer,
+    /// Send the values to a channel. The machine stops once the receiver is dropped.
+    Channel(Sender<i64>),
+    /// Call a function with each value. The machine stops when it returns `false`.
+    Callback(Arc<Mutex<dyn FnMut(i64) -> bool + Send>>),
+}
+
+impl Sink {
+    #[must_use]
+    pub fn callback(callback: impl FnMut(i64) -> bool + Send + 'static) -> Self {
+        Self::Callback(Arc::new(Mutex::new(callback)))
+    }
+}
+
+impl Debug for Sink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Buffer => write!(f, "Buffer"),
+            Self::Channel(sender) => f.debug_tuple("Channel").field(sender).finish(),
+            Self::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+impl Machine<'_> {
+    /// Send the values from `out` somewhere other than the output buffer.
+    #[must_use]
+    pub fn with_sink(mut self, sink: Sink) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    /// Queue values to be read by `in`.
+    #[must_use]
+    pub fn with_input(mut self, values: impl IntoIterator<Item = i64>) -> Self {
+        self.input.extend(values);
+        self
+    }
+
+    /// Queues a value to be read by `in`.
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(value);
+    }
+
+    /// Removes and returns the values collected in the output buffer. They still count towards
+    /// the output limit.
+    pub fn take_output(&mut self) -> Vec<i64> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Whether the machine is paused at an `in`, waiting for input. Running it again after
+    /// pushing input continues where it left off.
+    #[must_use]
+    pub const fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// Sends a value to the sink, returning `false` if the machine should stop.
+    pub(super) fn emit(&mut self, value: i64) -> bool {
+        match &self.sink {
+            Sink::Buffer => {
+                self.output.push(value);
+                self.emitted += 1;
+                self.output_limit != Some(self.emitted)
+            }
+            Sink::Channel(sender) => sender.send(value).is_ok(),
+            Sink::Callback(callback) => callback.lock().is_ok_and(|mut callback| callback(value)),
+        }
+    }
+}
+
+/// How [`Network::run`] finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkOutcome {
+    /// Every machine has stopped.
+    Halted,
+    /// The machines that have not stopped are all waiting for input that will never arrive.
+    Deadlock,
+    /// The step budget ran out.
+    LimitReached,
+}
+
+/// Several machines taking turns, with the output of some piped into the input of others.
+#[derive(Debug, Clone, Default)]
+pub struct Network<'a> {
+    machines: Vec<Machine<'a>>,
+    pipes: Vec<(usize, usize)>,
+}
+
+impl<'a> Network<'a> {
+    /// The number of steps a machine may take before the next one gets a turn.
+    const TIME_SLICE: usize = 1000;
+
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            machines: Vec::new(),
+            pipes: Vec::new(),
+        }
+    }
+
+    /// Adds a machine, returning its index.
+    pub fn add(&mut self, machine: Machine<'a>) -> usize {
+        self.machines.push(machine);
+        self.machines.len() - 1
+    }
+
+    /// Sends the buffered output of machine `from` to the input of machine `to`. Output piped to
+    /// several machines is copied to each of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either machine does not exist.
+    pub fn pipe(&mut self, from: usize, to: usize) {
+        assert!(from < self.machines.len() && to < self.machines.len());
+        self.pipes.push((from, to));
+    }
+
+    #[must_use]
+    pub fn machines(&self) -> &[Machine<'a>] {
+        &self.machines
+    }
+
+    /// Runs the machines in turns until they have all stopped, they are deadlocked, or
+    /// `max_steps` steps have been taken in total.
+    pub fn run(&mut self, max_steps: usize) -> NetworkOutcome {
+        let mut steps = 0;
+        loop {
+            let mut progress = false;
+            for machine in &mut self.machines {
+                for _ in 0..Self::TIME_SLICE.min(max_steps - steps) {
+                    if machine.stopped {
+                        break;
+                    }
+                    machine.step();
+                    if machine.waiting {
+                        break;
+                    }
+                    steps += 1;
+                    progress = true;
+                }
+            }
+            // Values moved along a pipe may wake up a waiting machine
+            progress |= self.deliver();
+            if self.machines.iter().all(|machine| machine.stopped) {
+                return NetworkOutcome::Halted;
+            }
+            if steps == max_steps {
+                return NetworkOutcome::LimitReached;
+            }
+            if !progress {
+                return NetworkOutcome::Deadlock;
+            }
+        }
+    }
+
+    /// Moves buffered output along the pipes, returning whether any values were moved.
+    fn deliver(&mut self) -> bool {
+        let mut delivered = false;
+        for from in 0..self.machines.len() {
+            if !self.pipes.iter().any(|&(source, _)| source == from) {
+                continue;
+            }
+            let values = self.machines[from].take_output();
+            delivered |= !values.is_empty();
+            for &(_, to) in self.pipes.iter().filter(|&&(source, _)| source == from) {
+                self.machines[to].input.extend(&values);
+            }
+        }
+        delivered
+    }
+}
+
+impl<'a> Index<usize> for Network<'a> {
+    type Output = Machine<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.machines[index]
+    }
+}
+
+impl IndexMut<usize> for Network<'_> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.machines[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::assembunny::{Dialect, Reg, assemble};
+
+    /// Doubles every value it reads, until it reads a zero.
+    const DOUBLER: &str = "\
+        loop: in a\n\
+            jnz a 2\n\
+            jnz 1 end\n\
+            cpy a b\n\
+        add: inc a\n\
+            dec b\n\
+            jnz b add\n\
+            out a\n\
+            jnz 1 loop\n\
+        end:\
+    ";
+
+    #[test]
+    fn test_input_and_waiting() {
+        let instructions = assemble(DOUBLER, Dialect::Io).unwrap();
+        let mut machine = Machine::new(&instructions).with_input([3, 5]);
+        machine.run();
+        assert!(machine.is_waiting());
+        assert!(!machine.is_stopped());
+        assert_eq!(machine.output(), [6, 10]);
+        machine.push_input(0);
+        machine.run();
+        assert!(machine.is_stopped());
+    }
+
+    #[test]
+    fn test_sinks() {
+        let instructions = assemble(DOUBLER, Dialect::Io).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let mut machine = Machine::new(&instructions)
+            .with_input([1, 2, 0])
+            .with_sink(Sink::Channel(sender));
+        machine.run();
+        assert!(machine.output().is_empty());
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [2, 4]);
+
+        let mut machine = Machine::new(&instructions)
+            .with_input([1, 2, 3, 0])
+            .with_sink(Sink::callback(|value| value < 4));
+        machine.run();
+        assert!(machine.is_stopped());
+        assert_eq!(machine[Reg::A], 4);
+    }
+
+    #[test]
+    fn test_network() {
+        let doubler = assemble(DOUBLER, Dialect::Io).unwrap();
+        let source = assemble("out 1\nout 2\nout 3\nout 0", Dialect::Io).unwrap();
+        let mut network = Network::new();
+        let first = network.add(Machine::new(&source));
+        let second = network.add(Machine::new(&doubler));
+        let third = network.add(Machine::new(&doubler));
+        network.pipe(first, second);
+        network.pipe(second, third);
+        assert_eq!(network.run(10_000), NetworkOutcome::Deadlock);
+        // The zero stops the second machine, so it never reaches the third one
+        assert!(network[second].is_stopped());
+        assert!(network[third].is_waiting());
+        assert_eq!(network[third].output(), [4, 8, 12]);
+
+        network[third].push_input(0);
+        assert_eq!(network.run(10_000), NetworkOutcome::Halted);
+
+        let forever = assemble("loop: out 1\njnz 1 loop", Dialect::Io).unwrap();
+        let mut network = Network::new();
+        let first = network.add(Machine::new(&forever));
+        let second = network.add(Machine::new(&doubler));
+        network.pipe(first, second);
+        assert_eq!(network.run(10_000), NetworkOutcome::LimitReached);
+        assert_eq!(network.run(0), NetworkOutcome::LimitReached);
+    }
+
+    #[test]
+    fn test_network_output_before_run() {
+        let doubler = assemble(DOUBLER, Dialect::Io).unwrap();
+        let source = assemble("out 1\nout 2\nout 3\nout 0", Dialect::Io).unwrap();
+        let mut machine = Machine::new(&source);
+        machine.run();
+        let mut network = Network::new();
+        let first = network.add(machine);
+        let second = network.add(Machine::new(&doubler));
+        network.pipe(first, second);
+        assert_eq!(network.run(10_000), NetworkOutcome::Halted);
+        assert_eq!(network[second].output(), [2, 4, 6]);
+    }
+
+    #[test]
+    fn test_network_output_limit() {
+        let doubler = assemble(DOUBLER, Dialect::Io).unwrap();
+        let forever = assemble("loop: out 1\njnz 1 loop", Dialect::Io).unwrap();
+        let mut network = Network::new();
+        let first = network.add(Machine::new(&forever).with_output_limit(5));
+        let second = network.add(Machine::new(&doubler));
+        network.pipe(first, second);
+        assert_eq!(network.run(10_000), NetworkOutcome::Deadlock);
+        assert!(network[first].is_stopped());
+        assert_eq!(network[second].output(), [2; 5]);
+    }
+}
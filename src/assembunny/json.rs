@@ -0,0 +1,170 @@
+/// Why a JSON value could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum JsonError {
+    Invalid,
+    MissingField(String),
+}
+
+/// The subset of JSON used by traces and snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Self>),
+    Object(Vec<(String, Self)>),
+}
+
+impl Json {
+    pub(super) fn get(&self, key: &str) -> Result<&Self, JsonError> {
+        self.fields()?
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .ok_or_else(|| JsonError::MissingField(key.to_string()))
+    }
+
+    pub(super) fn number<T: TryFrom<i64>>(&self) -> Result<T, JsonError> {
+        match *self {
+            Self::Number(value) => value.try_into().map_err(|_| JsonError::Invalid),
+            _ => Err(JsonError::Invalid),
+        }
+    }
+
+    pub(super) const fn bool(&self) -> Result<bool, JsonError> {
+        match *self {
+            Self::Bool(value) => Ok(value),
+            _ => Err(JsonError::Invalid),
+        }
+    }
+
+    pub(super) fn array(&self) -> Result<&[Self], JsonError> {
+        match self {
+            Self::Array(values) => Ok(values),
+            _ => Err(JsonError::Invalid),
+        }
+    }
+
+    pub(super) fn fields(&self) -> Result<&[(String, Self)], JsonError> {
+        match self {
+            Self::Object(fields) => Ok(fields),
+            _ => Err(JsonError::Invalid),
+        }
+    }
+
+    pub(super) fn string(&self) -> Result<&str, JsonError> {
+        match self {
+            Self::String(value) => Ok(value),
+            _ => Err(JsonError::Invalid),
+        }
+    }
+}
+
+pub(super) struct JsonParser<'a> {
+    rest: &'a str,
+}
+
+impl JsonParser<'_> {
+    /// Parses a single value, with nothing but whitespace around it.
+    pub(super) fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser {
+            rest: text.trim_ascii(),
+        };
+        let value = parser.value()?;
+        if parser.rest.is_empty() {
+            Ok(value)
+        } else {
+            Err(JsonError::Invalid)
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.rest = self.rest.trim_ascii_start();
+        if let Some(rest) = self.rest.strip_prefix(token) {
+            self.rest = rest;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), JsonError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(JsonError::Invalid)
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        if self.eat("null") {
+            Ok(Json::Null)
+        } else if self.eat("true") {
+            Ok(Json::Bool(true))
+        } else if self.eat("false") {
+            Ok(Json::Bool(false))
+        } else if self.eat("[") {
+            let items = self.list("]", Self::value)?;
+            Ok(Json::Array(items))
+        } else if self.eat("{") {
+            let fields = self.list("}", |parser| {
+                let key = parser.string()?;
+                parser.expect(":")?;
+                Ok((key, parser.value()?))
+            })?;
+            Ok(Json::Object(fields))
+        } else if self.rest.starts_with('"') {
+            Ok(Json::String(self.string()?))
+        } else {
+            let end = self
+                .rest
+                .find(|c: char| c != '-' && !c.is_ascii_digit())
+                .unwrap_or(self.rest.len());
+            let (number, rest) = self.rest.split_at(end);
+            self.rest = rest;
+            Ok(Json::Number(
+                number.parse().map_err(|_| JsonError::Invalid)?,
+            ))
+        }
+    }
+
+    /// Parses comma separated items, after the opening bracket.
+    fn list<T>(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T, JsonError>,
+    ) -> Result<Vec<T>, JsonError> {
+        let mut items = Vec::new();
+        if self.eat(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect("\"")?;
+        let mut value = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[i + 1..];
+                    return Ok(value);
+                }
+                '\\' => match chars.next() {
+                    Some((_, escaped @ ('"' | '\\' | '/'))) => value.push(escaped),
+                    _ => return Err(JsonError::Invalid),
+                },
+                c => value.push(c),
+            }
+        }
+        Err(JsonError::Invalid)
+    }
+}
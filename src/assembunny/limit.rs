@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+
+use super::{Instruction, Machine};
+
+/// How [`Machine::run_with_limit`] finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The machine stopped after this many steps.
+    Halted { steps: usize },
+    /// The machine is waiting for input after this many steps.
+    Waiting { steps: usize },
+    /// The step budget ran out before the machine stopped.
+    LimitReached,
+    /// The machine returned to an earlier state, so it will never stop. The states repeat every
+    /// `period` steps.
+    Looping { period: usize },
+}
+
+/// Everything that decides what the machine does next.
+#[derive(Debug, Clone)]
+struct State<'a> {
+    ip: usize,
+    registers: Vec<i64>,
+    instructions: Cow<'a, [Instruction]>,
+    /// Input is only ever taken from the queue while running, so the values left are told apart
+    /// by how many there are.
+    input: usize,
+    /// Only matters when there is an output limit.
+    outputs: Option<usize>,
+}
+
+impl<'a> Machine<'a> {
+    fn state(&self) -> State<'a> {
+        State {
+            ip: self.ip,
+            registers: self.registers.clone(),
+            instructions: self.instructions.clone(),
+            input: self.input.len(),
+            outputs: self.output_limit.map(|_| self.emitted),
+        }
+    }
+
+    fn is_in(&self, state: &State) -> bool {
+        self.ip == state.ip
+            && self.registers == state.registers
+            && self.input.len() == state.input
+            && state.outputs.is_none_or(|outputs| outputs == self.emitted)
+            && *self.instructions == *state.instructions
+    }
+
+    /// Runs at most `max_steps` steps, stopping early if the machine halts or is caught in a loop.
+    ///
+    /// Loops are found with Brent's algorithm, which only keeps one earlier state around. Steps
+    /// are always interpreted, regardless of the backend.
+    pub fn run_with_limit(&mut self, max_steps: usize) -> RunOutcome {
+        let mut saved = self.state();
+        let mut power = 1;
+        let mut period = 0;
+        for steps in 0..max_steps {
+            if self.stopped {
+                return RunOutcome::Halted { steps };
+            }
+            self.step();
+            if self.waiting {
+                return RunOutcome::Waiting { steps };
+            }
+            period += 1;
+            if self.is_in(&saved) {
+                return RunOutcome::Looping { period };
+            }
+            if period == power {
+                saved = self.state();
+                power *= 2;
+                period = 0;
+            }
+        }
+        if self.stopped {
+            RunOutcome::Halted { steps: max_steps }
+        } else {
+            RunOutcome::LimitReached
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, Reg, assemble};
+
+    #[test]
+    fn test_halted() {
+        let instructions = assemble("cpy 3 a\nloop: dec a\njnz a loop", Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions);
+        assert_eq!(machine.run_with_limit(100), RunOutcome::Halted { steps: 7 });
+        assert_eq!(machine.run_with_limit(100), RunOutcome::Halted { steps: 0 });
+
+        machine.reset();
+        assert_eq!(machine.run_with_limit(7), RunOutcome::Halted { steps: 7 });
+        machine.reset();
+        assert_eq!(machine.run_with_limit(6), RunOutcome::LimitReached);
+    }
+
+    #[test]
+    fn test_looping() {
+        let source = "\
+            cpy 5 b\n\
+            wait: dec b\n\
+            jnz b wait\n\
+            loop: inc a\n\
+            dec a\n\
+            out a\n\
+            jnz 1 loop\
+        ";
+        let instructions = assemble(source, Dialect::Clock).unwrap();
+        let mut machine = Machine::new(&instructions);
+        assert_eq!(
+            machine.run_with_limit(1000),
+            RunOutcome::Looping { period: 4 }
+        );
+        assert_eq!(machine[Reg::B], 0);
+
+        // Counting up forever never repeats a state
+        let instructions = assemble("loop: inc a\njnz 1 loop", Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions);
+        assert_eq!(machine.run_with_limit(1000), RunOutcome::LimitReached);
+    }
+
+    #[test]
+    fn test_waiting() {
+        let instructions = assemble("in a\nloop: in b\njnz 1 loop", Dialect::Io).unwrap();
+        let mut machine = Machine::new(&instructions).with_input([1, 2, 3]);
+        assert_eq!(
+            machine.run_with_limit(100),
+            RunOutcome::Waiting { steps: 5 }
+        );
+        assert_eq!(machine.registers(), [1, 3, 0, 0]);
+    }
+
+    #[test]
+    fn test_input_is_part_of_state() {
+        let instructions = assemble("loop: in a\njnz 1 loop", Dialect::Io).unwrap();
+        let mut machine = Machine::new(&instructions).with_input([7; 50]);
+        assert_eq!(
+            machine.run_with_limit(1000),
+            RunOutcome::Waiting { steps: 100 }
+        );
+    }
+
+    #[test]
+    fn test_output_limit_is_part_of_state() {
+        let instructions = assemble("loop: out a\njnz 1 loop", Dialect::Clock).unwrap();
+        let mut machine = Machine::new(&instructions).with_output_limit(10);
+        assert_eq!(
+            machine.run_with_limit(1000),
+            RunOutcome::Halted { steps: 19 }
+        );
+    }
+
+    #[test]
+    fn test_toggles_are_part_of_state() {
+        // Toggles between `inc a` and `dec a`, so `a` keeps returning to zero
+        let source = "\
+            loop: tgl 1\n\
+            inc a\n\
+            jnz 1 loop\
+        ";
+        let instructions = assemble(source, Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions).with_optimizer();
+        assert_eq!(
+            machine.run_with_limit(1000),
+            RunOutcome::Looping { period: 6 }
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use std::fmt::Display;
+
+use super::{Arithmetic, Instruction, Reg, RegOrValue, Registers};
+
+/// An instruction, or a loop fused into a single operation.
+///
+/// Fused operations are stored at the index of the first instruction they replace. The
+/// instructions they cover are kept, so jumps into the middle of a loop still work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Plain(Instruction),
+    /// `inc/dec target; inc/dec counter; jnz counter -2`, in either order.
+    Add {
+        target: Reg,
+        target_step: i64,
+        counter: Reg,
+        counter_step: i64,
+    },
+    /// `cpy source inner`, an [`Op::Add`] loop over `inner`, then `inc/dec outer; jnz outer -5`.
+    Mul {
+        source: RegOrValue,
+        target: Reg,
+        target_step: i64,
+        inner: Reg,
+        inner_step: i64,
+        outer: Reg,
+        outer_step: i64,
+    },
+}
+
+impl Op {
+    /// The number of instructions covered by the operation.
+    #[must_use]
+    pub const fn span(self) -> usize {
+        match self {
+            Self::Plain(_) => 1,
+            Self::Add { .. } => 3,
+            Self::Mul { .. } => 6,
+        }
+    }
+
+    /// Executes a fused operation, returning `false` if the loop would not terminate normally
+    /// for the current register values. The instructions should be executed one by one then.
+    /// The values in `registers` are in the order of `regs`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the target register if it overflows under [`Arithmetic::Checked`], leaving the
+    /// registers unchanged.
+    pub fn execute(
+        self,
+        regs: &Registers,
+        registers: &mut [i64],
+        arithmetic: Arithmetic,
+    ) -> Result<bool, Reg> {
+        let slot = |reg| regs.slot(reg);
+        match self {
+            Self::Plain(_) => Ok(false),
+            Self::Add {
+                target,
+                target_step,
+                counter,
+                counter_step,
+            } => {
+                let Some(count) = arithmetic.iterations(registers[slot(counter)], counter_step)
+                else {
+                    return Ok(false);
+                };
+                registers[slot(target)] = arithmetic
+                    .add(registers[slot(target)], i128::from(target_step) * count)
+                    .ok_or(target)?;
+                registers[slot(counter)] = 0;
+                Ok(true)
+            }
+            Self::Mul {
+                source,
+                target,
+                target_step,
+                inner,
+                inner_step,
+                outer,
+                outer_step,
+            } => {
+                let source = match source {
+                    RegOrValue::Reg(reg) => registers[slot(reg)],
+                    RegOrValue::Value(value) => value,
+                };
+                let (Some(inner_count), Some(outer_count)) = (
+                    arithmetic.iterations(source, inner_step),
+                    arithmetic.iterations(registers[slot(outer)], outer_step),
+                ) else {
+                    return Ok(false);
+                };
+                // Exact unless wrapping, where only the lowest 64 bits matter
+                let delta = i128::from(target_step)
+                    .wrapping_mul(inner_count)
+                    .wrapping_mul(outer_count);
+                registers[slot(target)] = arithmetic
+                    .add(registers[slot(target)], delta)
+                    .ok_or(target)?;
+                registers[slot(inner)] = 0;
+                registers[slot(outer)] = 0;
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Plain(instruction) => write!(f, "{instruction}"),
+            Self::Add {
+                target,
+                target_step,
+                counter,
+                counter_step,
+            } => write!(
+                f,
+                "add {target} += {} * {counter}; {counter} = 0",
+                -target_step * counter_step
+            ),
+            Self::Mul {
+                source,
+                target,
+                target_step,
+                inner,
+                inner_step,
+                outer,
+                outer_step,
+            } => write!(
+                f,
+                "mul {target} += {} * {source} * {outer}; {inner} = 0; {outer} = 0",
+                target_step * inner_step * outer_step
+            ),
+        }
+    }
+}
+
+/// The number of instructions covered by the longest fused operation.
+pub(super) const MAX_SPAN: usize = 6;
+
+/// Fuses the addition and multiplication loops in the program.
+#[must_use]
+pub fn optimize(instructions: &[Instruction]) -> Vec<Op> {
+    (0..instructions.len())
+        .map(|ip| optimize_at(instructions, ip))
+        .collect()
+}
+
+/// Updates the fused operations after the instruction at `changed` was modified.
+pub fn invalidate(ops: &mut [Op], instructions: &[Instruction], changed: usize) {
+    let first = changed.saturating_sub(MAX_SPAN - 1);
+    for (ip, op) in ops.iter_mut().enumerate().take(changed + 1).skip(first) {
+        *op = optimize_at(instructions, ip);
+    }
+}
+
+fn optimize_at(instructions: &[Instruction], ip: usize) -> Op {
+    match_mul(&instructions[ip..])
+        .or_else(|| match_add(&instructions[ip..]))
+        .unwrap_or(Op::Plain(instructions[ip]))
+}
+
+fn match_add(instructions: &[Instruction]) -> Option<Op> {
+    let [
+        first,
+        second,
+        Instruction::Jnz(RegOrValue::Reg(jump_reg), RegOrValue::Value(-2)),
+        ..,
+    ] = *instructions
+    else {
+        return None;
+    };
+    let (first_reg, first_step) = as_step(first)?;
+    let (second_reg, second_step) = as_step(second)?;
+    let ((target, target_step), (counter, counter_step)) = if first_reg == jump_reg {
+        ((second_reg, second_step), (first_reg, first_step))
+    } else {
+        ((first_reg, first_step), (second_reg, second_step))
+    };
+    (counter == jump_reg && target != counter).then_some(Op::Add {
+        target,
+        target_step,
+        counter,
+        counter_step,
+    })
+}
+
+fn match_mul(instructions: &[Instruction]) -> Option<Op> {
+    let [
+        Instruction::Cpy(source, RegOrValue::Reg(inner)),
+        ..,
+        step,
+        Instruction::Jnz(RegOrValue::Reg(jump_reg), RegOrValue::Value(-5)),
+    ] = *instructions.get(..6)?
+    else {
+        return None;
+    };
+    let Op::Add {
+        target,
+        target_step,
+        counter,
+        counter_step: inner_step,
+    } = match_add(&instructions[1..])?
+    else {
+        return None;
+    };
+    let (outer, outer_step) = as_step(step)?;
+    let independent = match source {
+        RegOrValue::Reg(reg) => ![target, inner, outer].contains(&reg),
+        RegOrValue::Value(_) => true,
+    };
+    (counter == inner && outer == jump_reg && ![target, inner].contains(&outer) && independent)
+        .then_some(Op::Mul {
+            source,
+            target,
+            target_step,
+            inner,
+            inner_step,
+            outer,
+            outer_step,
+        })
+}
+
+/// Matches `inc reg` and `dec reg`.
+const fn as_step(instruction: Instruction) -> Option<(Reg, i64)> {
+    match instruction {
+        Instruction::Inc(RegOrValue::Reg(reg)) => Some((reg, 1)),
+        Instruction::Dec(RegOrValue::Reg(reg)) => Some((reg, -1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, Machine, parse};
+
+    #[test]
+    fn test_optimize() {
+        let instructions = parse(
+            "\
+            cpy b c\n\
+            inc a\n\
+            dec c\n\
+            jnz c -2\n\
+            dec d\n\
+            jnz d -5\n\
+            dec d\n\
+            inc c\n\
+            jnz d -2\
+            ",
+            Dialect::Basic,
+        )
+        .unwrap();
+        let ops = optimize(&instructions);
+        assert_eq!(
+            ops[0],
+            Op::Mul {
+                source: RegOrValue::Reg(Reg::B),
+                target: Reg::A,
+                target_step: 1,
+                inner: Reg::C,
+                inner_step: -1,
+                outer: Reg::D,
+                outer_step: -1,
+            }
+        );
+        assert!(matches!(ops[1], Op::Add { .. }));
+        assert_eq!(
+            ops[6],
+            Op::Add {
+                target: Reg::C,
+                target_step: 1,
+                counter: Reg::D,
+                counter_step: -1,
+            }
+        );
+        for ip in [2, 3, 4, 5, 7, 8] {
+            assert_eq!(ops[ip], Op::Plain(instructions[ip]));
+        }
+    }
+
+    #[test]
+    fn test_fused_matches_interpreter() {
+        let instructions = parse(
+            "\
+            cpy 3 b\n\
+            cpy 4 d\n\
+            cpy b c\n\
+            inc a\n\
+            dec c\n\
+            jnz c -2\n\
+            dec d\n\
+            jnz d -5\n\
+            cpy -5 d\n\
+            dec c\n\
+            inc d\n\
+            jnz d -2\
+            ",
+            Dialect::Basic,
+        )
+        .unwrap();
+        let mut plain = Machine::new(&instructions);
+        plain.run();
+        let mut optimized = Machine::new(&instructions).with_optimizer();
+        optimized.run();
+        assert_eq!(plain.registers(), [12, 3, -5, 0]);
+        assert_eq!(optimized.registers(), plain.registers());
+    }
+
+    #[test]
+    fn test_fused_falls_back() {
+        // Counting up from a positive value never reaches zero without overflowing
+        let instructions = parse("inc a\ninc b\njnz b -2", Dialect::Basic).unwrap();
+        let ops = optimize(&instructions);
+        let regs = Registers::of(&instructions);
+        let mut registers = [0, 5, 0, 0];
+        assert_eq!(
+            ops[0].execute(&regs, &mut registers, Arithmetic::Checked),
+            Ok(false)
+        );
+        registers = [0, -5, 0, 0];
+        assert_eq!(
+            ops[0].execute(&regs, &mut registers, Arithmetic::Checked),
+            Ok(true)
+        );
+        assert_eq!(registers, [5, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_toggle_invalidates() {
+        let instructions = parse(
+            "\
+            cpy 2 c\n\
+            tgl c\n\
+            cpy 3 b\n\
+            inc a\n\
+            dec b\n\
+            jnz b -2\
+            ",
+            Dialect::Toggle,
+        )
+        .unwrap();
+        let mut plain = Machine::new(&instructions);
+        plain.run();
+        let mut optimized = Machine::new(&instructions).with_optimizer();
+        optimized.run();
+        assert_eq!(plain.registers(), [-3, 0, 2, 0]);
+        assert_eq!(optimized.registers(), plain.registers());
+    }
+}
@@ -0,0 +1,238 @@
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter, Result, Write};
+
+use super::cfg::{BasicBlock, ControlFlowGraph};
+use super::{Instruction, Machine};
+
+/// How often one basic block was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockProfile {
+    pub block: BasicBlock,
+    /// Executions of the first instruction of the block.
+    pub entries: u64,
+    /// Executions of every instruction in the block.
+    pub steps: u64,
+}
+
+/// Execution counts gathered by a machine with [`Machine::with_profiler`].
+///
+/// Blocks are those of the original program, so counts of instructions modified by `tgl` are
+/// attributed to the block they were in at the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    instructions: Vec<Instruction>,
+    counts: Vec<u64>,
+    blocks: Vec<BlockProfile>,
+}
+
+impl Profile {
+    #[must_use]
+    pub fn new(instructions: &[Instruction], counts: &[u64]) -> Self {
+        let cfg = ControlFlowGraph::new(instructions);
+        let mut blocks = cfg
+            .blocks()
+            .iter()
+            .map(|&block| BlockProfile {
+                block,
+                entries: counts[block.start],
+                steps: counts[block.start..block.end].iter().sum(),
+            })
+            .collect::<Vec<_>>();
+        blocks.sort_by_key(|profile| (Reverse(profile.steps), profile.block.start));
+        Self {
+            instructions: instructions.to_vec(),
+            counts: counts.to_vec(),
+            blocks,
+        }
+    }
+
+    /// The number of steps taken. A fused loop counts as one step.
+    #[must_use]
+    pub fn total_steps(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// How many times each instruction was executed, by index.
+    #[must_use]
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The basic blocks, hottest first.
+    #[must_use]
+    pub fn blocks(&self) -> &[BlockProfile] {
+        &self.blocks
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let mut json = format!(
+            "{{\"total_steps\":{},\"instructions\":[",
+            self.total_steps()
+        );
+        for (ip, (instruction, count)) in self.instructions.iter().zip(&self.counts).enumerate() {
+            let comma = if ip == 0 { "" } else { "," };
+            let _ = write!(
+                json,
+                "{comma}{{\"ip\":{ip},\"instruction\":\"{instruction}\",\"count\":{count}}}"
+            );
+        }
+        json.push_str("],\"blocks\":[");
+        for (i, profile) in self.blocks.iter().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            let BlockProfile {
+                block: BasicBlock { start, end },
+                entries,
+                steps,
+            } = profile;
+            let _ = write!(
+                json,
+                "{comma}{{\"start\":{start},\"end\":{end},\"entries\":{entries},\"steps\":{steps}}}"
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let total = self.total_steps();
+        writeln!(f, "Total steps: {total}")?;
+        writeln!(f)?;
+        writeln!(f, "  block      entries        steps   share")?;
+        for profile in self.blocks.iter().filter(|profile| profile.steps > 0) {
+            let BasicBlock { start, end } = profile.block;
+            #[allow(clippy::cast_precision_loss, reason = "Only used for display")]
+            let share = 100.0 * profile.steps as f64 / total as f64;
+            writeln!(
+                f,
+                "{:>7} {:>12} {:>12} {share:>6.1}%",
+                format!("{start}..{end}"),
+                profile.entries,
+                profile.steps,
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "  ip        count  instruction")?;
+        for (ip, (instruction, count)) in self.instructions.iter().zip(&self.counts).enumerate() {
+            writeln!(f, "{ip:>4} {count:>12}  {instruction}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Machine<'_> {
+    /// Count how many times each instruction is executed. Profiled machines are always
+    /// interpreted.
+    #[must_use]
+    pub fn with_profiler(mut self) -> Self {
+        self.profile = Some(vec![0; self.program.len()]);
+        self
+    }
+
+    /// The execution counts since the machine was created or reset, if profiling is enabled.
+    #[must_use]
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile
+            .as_deref()
+            .map(|counts| Profile::new(self.program, counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Backend, Dialect, assemble};
+
+    const PROGRAM: &str = "\
+            cpy 3 b\n\
+        outer:\n\
+            cpy 2 c\n\
+        inner:\n\
+            inc a\n\
+            dec c\n\
+            jnz c inner\n\
+            dec b\n\
+            jnz b outer\
+    ";
+
+    #[test]
+    fn test_counts() {
+        let instructions = assemble(PROGRAM, Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions)
+            .with_backend(Backend::Compiled)
+            .with_profiler();
+        machine.run();
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.counts(), [1, 3, 6, 6, 6, 3, 3]);
+        assert_eq!(profile.total_steps(), 28);
+        let blocks = profile
+            .blocks()
+            .iter()
+            .map(|profile| (profile.block.start, profile.entries, profile.steps))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, [(2, 6, 18), (5, 3, 6), (1, 3, 3), (0, 1, 1)]);
+
+        machine.reset();
+        assert_eq!(machine.profile().unwrap().total_steps(), 0);
+        assert_eq!(Machine::new(&instructions).profile(), None);
+    }
+
+    #[test]
+    fn test_fused_loops_count_once() {
+        let instructions = assemble(PROGRAM, Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions).with_optimizer().with_profiler();
+        machine.run();
+        // Both loops together are one multiplication
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.counts(), [1, 1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_waiting_is_not_counted() {
+        let instructions = assemble("in a\nin b", Dialect::Io).unwrap();
+        let mut machine = Machine::new(&instructions).with_input([3]).with_profiler();
+        for _ in 0..3 {
+            machine.run();
+        }
+        assert!(machine.is_waiting());
+        assert_eq!(machine.profile().unwrap().counts(), [1, 0]);
+        machine.push_input(5);
+        machine.run();
+        assert_eq!(machine.profile().unwrap().counts(), [1, 1]);
+    }
+
+    #[test]
+    fn test_report() {
+        let instructions = assemble("cpy 2 a\nloop: dec a\njnz a loop", Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions).with_profiler();
+        machine.run();
+        let profile = machine.profile().unwrap();
+        assert_eq!(
+            profile.to_string(),
+            "\
+Total steps: 5
+
+  block      entries        steps   share
+   1..3            2            4   80.0%
+   0..1            1            1   20.0%
+
+  ip        count  instruction
+   0            1  cpy 2 a
+   1            2  dec a
+   2            2  jnz a -1
+"
+        );
+        assert_eq!(
+            profile.to_json(),
+            "{\"total_steps\":5,\"instructions\":[\
+                {\"ip\":0,\"instruction\":\"cpy 2 a\",\"count\":1},\
+                {\"ip\":1,\"instruction\":\"dec a\",\"count\":2},\
+                {\"ip\":2,\"instruction\":\"jnz a -1\",\"count\":2}],\
+            \"blocks\":[\
+                {\"start\":1,\"end\":3,\"entries\":2,\"steps\":4},\
+                {\"start\":0,\"end\":1,\"entries\":1,\"steps\":1}]}"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::ops::Range;
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use super::{Machine, Reg};
+
+/// The number of candidates in the first batch. Each batch is twice as large as the one before,
+/// so early answers are found without trying the whole range.
+const FIRST_BATCH: i64 = 256;
+
+impl Machine<'_> {
+    /// Finds the smallest value in `candidates` that, stored in `reg`, makes `accept` return true
+    /// once the machine has run.
+    ///
+    /// Each candidate runs on its own copy of the machine, in parallel. Any other registers, input
+    /// or limits set on this machine are copied too, so the search does not need to run out of
+    /// steps: use [`Machine::with_output_limit`] for programs that never halt.
+    #[must_use]
+    pub fn find_initial_value(
+        &self,
+        reg: Reg,
+        candidates: Range<i64>,
+        accept: impl Fn(&Machine) -> bool + Sync,
+    ) -> Option<i64> {
+        self.find_initial_value_by(reg, candidates, |machine| {
+            machine.run();
+            accept(machine)
+        })
+    }
+
+    /// Like [`Machine::find_initial_value`], but `test` runs the machine itself, and may stop it
+    /// as early as it likes.
+    #[must_use]
+    pub fn find_initial_value_by(
+        &self,
+        reg: Reg,
+        candidates: Range<i64>,
+        test: impl Fn(&mut Machine) -> bool + Sync,
+    ) -> Option<i64> {
+        let mut start = candidates.start;
+        let mut batch = FIRST_BATCH;
+        while start < candidates.end {
+            let end = start.saturating_add(batch).min(candidates.end);
+            let found = (start..end).into_par_iter().find_first(|&value| {
+                let mut machine = self.clone();
+                machine[reg] = value;
+                test(&mut machine)
+            });
+            if found.is_some() {
+                return found;
+            }
+            start = end;
+            batch = batch.saturating_mul(2);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, parse};
+
+    #[test]
+    fn test_find_initial_value() {
+        // Outputs `a * a + d`
+        let source = "cpy a b\ncpy a c\ninc d\ndec c\njnz c -2\ndec b\njnz b -5\nout d";
+        let instructions = parse(source, Dialect::Clock).unwrap();
+        let machine = Machine::new(&instructions).with_optimizer();
+        let square = |machine: &Machine, target| {
+            machine.find_initial_value(Reg::A, 1..1000, |machine| machine.output() == [target])
+        };
+        assert_eq!(square(&machine, 169), Some(13));
+        assert_eq!(square(&machine, 170), None);
+
+        // Values set on the machine are kept
+        let mut machine = machine;
+        machine[Reg::D] = 11;
+        assert_eq!(square(&machine, 36), Some(5));
+    }
+
+    #[test]
+    fn test_find_initial_value_by() {
+        // Counts `a` up, printing it, and never stops
+        let instructions = parse("out a\ninc a\njnz 1 -2", Dialect::Clock).unwrap();
+        let machine = Machine::new(&instructions).with_output_limit(3);
+        let found = machine.find_initial_value_by(Reg::A, -1000..1000, |machine| {
+            while machine.output().len() < 3 {
+                machine.step();
+            }
+            machine.output() == [7, 8, 9]
+        });
+        assert_eq!(found, Some(7));
+        // Candidates past the first batch
+        let found =
+            machine.find_initial_value(Reg::A, 0..i64::MAX, |machine| machine.output()[0] > 5000);
+        assert_eq!(found, Some(5001));
+    }
+}
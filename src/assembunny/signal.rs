@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use super::{Dialect, Expr, Instruction, Machine, Reg, RegOrValue, SymbolicMachine, parse};
+
+/// Give up on proving that a signal repeats after this many steps.
+const MAX_STEPS: usize = 1 << 24;
+
+/// Give up on searching for a signal after trying this many initial values.
+const MAX_INITIAL_VALUE: i64 = 1 << 16;
+
+/// An output sequence to search for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signal {
+    /// The values, repeated forever.
+    Periodic(Vec<i64>),
+    /// The values, followed by anything (including nothing, if the machine stops).
+    Prefix(Vec<i64>),
+}
+
+impl Signal {
+    /// The value expected at `index`, or `None` if any value is accepted.
+    fn expected(&self, index: usize) -> Option<i64> {
+        match self {
+            Self::Periodic(values) => values.get(index.checked_rem(values.len())?).copied(),
+            Self::Prefix(values) => values.get(index).copied(),
+        }
+    }
+
+    /// Whether outputs that repeat every `cycle` values match the signal forever.
+    fn repeats_every(&self, cycle: usize) -> bool {
+        match self {
+            Self::Periodic(values) => {
+                let period = values.len();
+                (0..period).all(|i| values[i] == values[(i + cycle) % period])
+            }
+            Self::Prefix(_) => true,
+        }
+    }
+}
+
+impl Machine<'_> {
+    /// Whether the machine outputs `signal`.
+    ///
+    /// The state is recorded every time the machine is about to output a value. Once a state
+    /// repeats, the outputs since its first occurrence will repeat forever too, so the machine
+    /// produces a periodic signal if every output so far matched, and the cycle lines up with the
+    /// signal's period.
+    pub fn produces(&mut self, signal: &Signal) -> bool {
+        let mut seen = HashMap::new();
+        for _ in 0..MAX_STEPS {
+            let outputs = self.output().len();
+            if let Signal::Prefix(values) = signal
+                && outputs >= values.len()
+            {
+                return true;
+            }
+            if self.is_stopped() {
+                return false;
+            }
+            if let Instruction::Out(_) = self.instructions()[self.ip()]
+                && let Some(previous) = seen.insert((self.ip(), self.registers().to_vec()), outputs)
+            {
+                return signal.repeats_every(outputs - previous);
+            }
+            self.step();
+            if let Some(&value) = self.output().get(outputs)
+                && signal
+                    .expected(outputs)
+                    .is_some_and(|expected| expected != value)
+            {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+/// Finds the smallest non-negative value of register `a` that makes the program output `signal`.
+///
+/// Programs that print the bits of `a + k` are solved directly, and the answer is checked by
+/// running it. Any other program is simulated for each candidate value, giving up after
+/// [`MAX_INITIAL_VALUE`].
+#[must_use]
+pub fn find_signal_value(instructions: &[Instruction], signal: &Signal) -> Option<i64> {
+    let machine = Machine::new(instructions).with_optimizer();
+    if let Signal::Periodic(values) = signal
+        && let Some(offset) = bits_offset(instructions)
+    {
+        let solved = solve_bits(offset, values);
+        let produces = |value| {
+            let mut machine = machine.clone();
+            machine[Reg::A] = value;
+            machine.produces(signal)
+        };
+        if solved.is_none_or(produces) {
+            return solved;
+        }
+    }
+    machine.find_initial_value_by(Reg::A, 0..MAX_INITIAL_VALUE, |machine| {
+        machine.produces(signal)
+    })
+}
+
+/// If the program ends like [`BITS_PROGRAM`], printing the bits of `a`, lowest first, over and
+/// over, and everything before that adds a constant `k` to `a`, returns `k`.
+///
+/// The printer jumps back to the `cpy d a` in front of it, so that `cpy d a` is part of the
+/// printer, and the instructions before it have to fall through to it.
+pub(super) fn bits_offset(instructions: &[Instruction]) -> Option<i64> {
+    let template = parse(BITS_PROGRAM, Dialect::Clock).ok()?;
+    let printer = &template[BITS_PRINTER - 1..];
+    let start = instructions.len().checked_sub(printer.len())?;
+    if instructions[start..] != *printer {
+        return None;
+    }
+    // Run up to and including the `cpy d a`, which is as far as the printer jumps back
+    let preamble = &instructions[..=start];
+    let stays_inside = |(ip, &instruction): (usize, &Instruction)| match instruction {
+        Instruction::Jnz(RegOrValue::Value(0), _)
+        | Instruction::Cpy(..)
+        | Instruction::Inc(_)
+        | Instruction::Dec(_)
+        | Instruction::Out(_) => true,
+        Instruction::Jnz(_, RegOrValue::Value(offset)) => isize::try_from(offset)
+            .ok()
+            .and_then(|offset| ip.checked_add_signed(offset))
+            .is_some_and(|target| target < preamble.len()),
+        Instruction::Jnz(..) | Instruction::Tgl(_) | Instruction::In(_) => false,
+    };
+    if !preamble.iter().enumerate().all(stays_inside) {
+        return None;
+    }
+    let mut machine = SymbolicMachine::new(preamble);
+    machine.run().ok()?;
+    if machine[Reg::A] != machine[Reg::D] || !machine.outputs().is_empty() {
+        return None;
+    }
+    machine[Reg::A]
+        .checked_sub(&Expr::reg(Reg::A))?
+        .as_constant()
+        .filter(|&offset| offset >= 0)
+}
+
+/// Finds the smallest `a` such that the bits of `a + offset`, repeated, equal `values` repeated.
+pub(super) fn solve_bits(offset: i64, values: &[i64]) -> Option<i64> {
+    if values.iter().any(|&value| value != 0 && value != 1) {
+        return None;
+    }
+    let period = (1..=values.len())
+        .find(|&period| {
+            values.len().is_multiple_of(period)
+                && values
+                    .iter()
+                    .zip(values.iter().cycle().skip(period))
+                    .all(|(a, b)| a == b)
+        })
+        .unwrap_or_default();
+    let values = &values[..period];
+    // Zero prints a single zero bit
+    if values.iter().all(|&value| value == 0) {
+        return (offset == 0).then_some(0);
+    }
+    // Otherwise the highest printed bit is always a one, and the bits of the sum must be
+    // `values` repeated some number of times.
+    if values.last() != Some(&1) {
+        return None;
+    }
+    let period = u32::try_from(period)
+        .ok()
+        .filter(|&period| period < i64::BITS - 1)?;
+    let chunk = values.iter().rev().fold(0, |s, &b| (s << 1) | b);
+    let mut sum = chunk;
+    let mut shift = period;
+    while sum < offset {
+        sum = sum.checked_add(chunk.checked_shl(shift).filter(|c| c >> shift == chunk)?)?;
+        shift = shift.checked_add(period)?;
+    }
+    Some(sum - offset)
+}
+
+/// Where [`BITS_PROGRAM`] starts printing the bits of `a`.
+pub(super) const BITS_PRINTER: usize = 9;
+
+/// Outputs the bits of `a + 4 * 633`, lowest first. Day 25 inputs differ only in the two constants.
+pub const BITS_PROGRAM: &str = "\
+    cpy a d\n\
+    cpy 4 c\n\
+    cpy 633 b\n\
+    inc d\n\
+    dec b\n\
+    jnz b -2\n\
+    dec c\n\
+    jnz c -5\n\
+    cpy d a\n\
+    jnz 0 0\n\
+    cpy a b\n\
+    cpy 0 a\n\
+    cpy 2 c\n\
+    jnz b 2\n\
+    jnz 1 6\n\
+    dec b\n\
+    dec c\n\
+    jnz c -4\n\
+    inc a\n\
+    jnz 1 -7\n\
+    cpy 2 b\n\
+    jnz c 2\n\
+    jnz 1 4\n\
+    dec b\n\
+    dec c\n\
+    jnz 1 -4\n\
+    jnz 0 0\n\
+    out b\n\
+    jnz a -19\n\
+    jnz 1 -21\
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_clock(source: &str) -> Vec<Instruction> {
+        parse(source, Dialect::Clock).unwrap()
+    }
+
+    #[test]
+    fn test_produces() {
+        let clock = Signal::Periodic(vec![0, 1]);
+        let instructions = parse_clock(BITS_PROGRAM);
+        let mut machine = Machine::new(&instructions);
+        machine[Reg::A] = 198 + 0b1010_1010_1010;
+        // Alternates for the first 10 outputs, but not after that
+        assert!(!machine.produces(&clock));
+
+        let instructions = parse_clock("out 0\nout 1\nout 0\nout 1");
+        assert!(!Machine::new(&instructions).produces(&clock));
+        let instructions = parse_clock("out 0\nout 1\njnz 1 -2");
+        assert!(Machine::new(&instructions).produces(&clock));
+    }
+
+    #[test]
+    fn test_find_signal_value() {
+        let instructions = parse_clock(BITS_PROGRAM);
+        // 2532 + 15 = 0b1001_1111_0011
+        let signal = Signal::Prefix(vec![1, 1, 0, 0]);
+        assert_eq!(find_signal_value(&instructions, &signal), Some(15));
+        // 2532 + 1563 = 0b1111_1111_1111
+        let signal = Signal::Periodic(vec![1]);
+        assert_eq!(find_signal_value(&instructions, &signal), Some(1563));
+        // 2532 + 1116 = 0b1110_0100_0000
+        let signal = Signal::Periodic(vec![0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 1]);
+        assert_eq!(find_signal_value(&instructions, &signal), Some(1116));
+        let signal = Signal::Periodic(vec![1, 1, 0]);
+        assert_eq!(find_signal_value(&instructions, &signal), None);
+        // 2532 + 978 = 0b1101_1011_0110
+        let signal = Signal::Periodic(vec![0, 1, 1, 0, 1, 1]);
+        assert_eq!(find_signal_value(&instructions, &signal), Some(978));
+    }
+
+    #[test]
+    fn test_bits_offset() {
+        let instructions = parse_clock(BITS_PROGRAM);
+        assert_eq!(bits_offset(&instructions), Some(2532));
+        // Any preamble that adds a constant will do
+        let source = BITS_PROGRAM.replacen("cpy a d", "cpy a d\ninc d\ninc d", 1);
+        assert_eq!(bits_offset(&parse_clock(&source)), Some(2534));
+        let source = BITS_PROGRAM.replacen("cpy a d", "cpy a d\ninc d\ninc a", 1);
+        assert_eq!(bits_offset(&parse_clock(&source)), Some(2533));
+        let source = BITS_PROGRAM.replacen("cpy a d", "cpy a d\ninc d\ncpy 1 a", 1);
+        assert_eq!(bits_offset(&parse_clock(&source)), Some(2533));
+        let source = BITS_PROGRAM.replacen("cpy a d", "cpy a d\nout d", 1);
+        assert_eq!(bits_offset(&parse_clock(&source)), None);
+        let source = BITS_PROGRAM.replacen(
+            "cpy a d",
+            "cpy a d\ninc d\ncpy d b\ninc d\ndec b\njnz b -2",
+            1,
+        );
+        assert_eq!(bits_offset(&parse_clock(&source)), None);
+
+        // The printer jumps back to whatever comes before it, here adding one each time
+        let (_, printer) = BITS_PROGRAM.split_once("cpy d a\n").unwrap();
+        let instructions = parse_clock(&format!("inc a\n{printer}"));
+        assert_eq!(bits_offset(&instructions), None);
+        // After the first pass it only ever prints ones
+        let signal = Signal::Periodic(vec![1]);
+        assert_eq!(find_signal_value(&instructions, &signal), Some(0));
+        // Jumping into the middle of the printer
+        let source = BITS_PROGRAM.replacen("cpy a d", "cpy a d\njnz 1 20", 1);
+        assert_eq!(bits_offset(&parse_clock(&source)), None);
+    }
+
+    #[test]
+    fn test_symbolic_matches_simulation() {
+        let instructions = parse_clock(BITS_PROGRAM);
+        for values in [vec![1, 0, 1], vec![0, 1, 1]] {
+            let signal = Signal::Periodic(values);
+            let mut machine = Machine::new(&instructions).with_optimizer();
+            let simulated = (0..1 << 12).find(|&initial_value| {
+                machine.reset();
+                machine[Reg::A] = initial_value;
+                machine.produces(&signal)
+            });
+            assert_eq!(find_signal_value(&instructions, &signal), simulated);
+        }
+    }
+}
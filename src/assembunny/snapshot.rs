@@ -0,0 +1,373 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use super::json::{Json, JsonError, JsonParser};
+use super::{Instruction, Machine, Overflow, ParseError, Reg, optimize};
+
+/// The complete state of a [`Machine`], which can be saved and restored later.
+///
+/// ```text
+/// {"ip":3,"stopped":false,"registers":{"a":2,"b":0,"c":0,"d":0},"instructions":["cpy 2 a","tgl a","tgl a","inc a"],"input":[],"output":[],"overflow":null}
+/// ```
+///
+/// Settings such as the backend, limits and tracer belong to the machine, and are left alone by
+/// [`Machine::restore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub ip: usize,
+    pub stopped: bool,
+    /// Every register the program uses, by name.
+    pub registers: Vec<(Reg, i64)>,
+    /// The instructions, as modified by `tgl`.
+    pub instructions: Vec<Instruction>,
+    /// Input that has not been read yet.
+    pub input: Vec<i64>,
+    /// Output collected so far.
+    pub output: Vec<i64>,
+    pub overflow: Option<Overflow>,
+}
+
+impl Snapshot {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let registers = self
+            .registers
+            .iter()
+            .map(|(reg, value)| format!("\"{reg}\":{value}"))
+            .collect::<Vec<_>>()
+            .join(",");
+        let instructions = self
+            .instructions
+            .iter()
+            .map(|instruction| format!("\"{instruction}\""))
+            .collect::<Vec<_>>()
+            .join(",");
+        let overflow = match self.overflow {
+            Some(Overflow { ip, reg }) => format!("{{\"ip\":{ip},\"reg\":\"{reg}\"}}"),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"ip\":{},\"stopped\":{},\"registers\":{{{registers}}},\"instructions\":[{instructions}],\"input\":{},\"output\":{},\"overflow\":{overflow}}}",
+            self.ip,
+            self.stopped,
+            json_list(&self.input),
+            json_list(&self.output),
+        )
+    }
+
+    /// Parses a snapshot written by [`Snapshot::to_json`]. Fields may come in any order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not a JSON object with the fields of a snapshot.
+    pub fn from_json(text: &str) -> Result<Self, SnapshotError> {
+        let value = JsonParser::parse(text)?;
+        let ip = value.get("ip")?.number()?;
+        let stopped = value.get("stopped")?.bool()?;
+        let numbers = |field: &str| -> Result<Vec<i64>, SnapshotError> {
+            Ok(value
+                .get(field)?
+                .array()?
+                .iter()
+                .map(Json::number)
+                .collect::<Result<_, _>>()?)
+        };
+        let registers = value
+            .get("registers")?
+            .fields()?
+            .iter()
+            .map(|(name, value)| Ok((name.parse()?, value.number()?)))
+            .collect::<Result<_, SnapshotError>>()?;
+        let instructions = value
+            .get("instructions")?
+            .array()?
+            .iter()
+            .map(|instruction| Ok(instruction.string()?.parse()?))
+            .collect::<Result<_, SnapshotError>>()?;
+        let overflow = match value.get("overflow")? {
+            Json::Null => None,
+            overflow => Some(Overflow {
+                ip: overflow.get("ip")?.number()?,
+                reg: overflow.get("reg")?.string()?.parse()?,
+            }),
+        };
+        Ok(Self {
+            ip,
+            stopped,
+            registers,
+            instructions,
+            input: numbers("input")?,
+            output: numbers("output")?,
+            overflow,
+        })
+    }
+}
+
+fn json_list(values: &[i64]) -> String {
+    let values = values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("[{values}]")
+}
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Invalid snapshot")]
+    Json,
+    #[error("Snapshot has no field {0:?}")]
+    MissingField(String),
+    #[error(transparent)]
+    Instruction(#[from] ParseError),
+    #[error("Snapshot is of a different program")]
+    ProgramMismatch,
+    #[error("The program does not use register {0}")]
+    UnknownRegister(Reg),
+    #[error("Instruction {0} is past the end of the program")]
+    InvalidIp(usize),
+}
+
+impl From<JsonError> for SnapshotError {
+    fn from(error: JsonError) -> Self {
+        match error {
+            JsonError::Invalid => Self::Json,
+            JsonError::MissingField(field) => Self::MissingField(field),
+        }
+    }
+}
+
+impl Machine<'_> {
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            stopped: self.stopped,
+            registers: self.regs().map(|reg| (reg, self[reg])).collect(),
+            instructions: self.instructions.to_vec(),
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            overflow: self.overflow,
+        }
+    }
+
+    /// Returns the machine to the state in the snapshot. Registers missing from the snapshot are
+    /// cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the machine unchanged, if the snapshot names a register the
+    /// program does not use, its instructions could not have come from this program, or it is
+    /// running past the end of the program.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if let Some(&(reg, _)) = snapshot
+            .registers
+            .iter()
+            .find(|&&(reg, _)| self.regs.index(reg).is_none())
+        {
+            return Err(SnapshotError::UnknownRegister(reg));
+        }
+        // Toggling changes an instruction at most twice before it toggles back and forth
+        let is_toggled = |(&original, &instruction): (&Instruction, &Instruction)| {
+            instruction == original
+                || instruction == original.toggle()
+                || instruction == original.toggle().toggle()
+        };
+        if snapshot.instructions.len() != self.program.len()
+            || !self
+                .program
+                .iter()
+                .zip(&snapshot.instructions)
+                .all(is_toggled)
+        {
+            return Err(SnapshotError::ProgramMismatch);
+        }
+        // Only a stopped machine can be at the end
+        let len = self.program.len();
+        if snapshot.ip > len || (snapshot.ip == len && !snapshot.stopped) {
+            return Err(SnapshotError::InvalidIp(snapshot.ip));
+        }
+
+        self.instructions = if snapshot.instructions == self.program {
+            Cow::Borrowed(self.program)
+        } else {
+            Cow::Owned(snapshot.instructions.clone())
+        };
+        if let Some(ops) = &mut self.optimized {
+            *ops = optimize(&self.instructions);
+        }
+        self.compiled = None;
+        self.ip = snapshot.ip;
+        self.registers.fill(0);
+        for &(reg, value) in &snapshot.registers {
+            self[reg] = value;
+        }
+        self.stopped = snapshot.stopped;
+        self.waiting = false;
+        self.input = VecDeque::from(snapshot.input.clone());
+        self.output.clone_from(&snapshot.output);
+        self.emitted = snapshot.output.len();
+        self.overflow = snapshot.overflow;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Backend, Dialect, parse};
+
+    /// Day 23 example, followed by a multiplication loop.
+    const PROGRAM: &str = "\
+        cpy 2 a\n\
+        tgl a\n\
+        tgl a\n\
+        tgl a\n\
+        cpy 1 a\n\
+        dec a\n\
+        dec a\n\
+        cpy 3 d\n\
+        cpy 4 c\n\
+        inc b\n\
+        dec c\n\
+        jnz c -2\n\
+        dec d\n\
+        jnz d -5\
+    ";
+
+    #[test]
+    fn test_round_trip() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions);
+        for _ in 0..3 {
+            machine.step();
+        }
+        let snapshot = machine.snapshot();
+        assert_eq!(snapshot.ip, 3);
+        assert_eq!(
+            snapshot.instructions[3],
+            parse("inc a", Dialect::Basic).unwrap()[0]
+        );
+        assert_eq!(Snapshot::from_json(&snapshot.to_json()).unwrap(), snapshot);
+
+        let snapshot = Snapshot {
+            input: vec![1, -2],
+            output: vec![3],
+            overflow: Some(Overflow { ip: 9, reg: Reg::B }),
+            ..snapshot
+        };
+        assert_eq!(Snapshot::from_json(&snapshot.to_json()).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn test_resume() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut expected = Machine::new(&instructions);
+        expected.run();
+
+        let mut machine = Machine::new(&instructions);
+        for _ in 0..10 {
+            machine.step();
+        }
+        let saved = machine.snapshot().to_json();
+        for backend in [Backend::Interpreter, Backend::Compiled] {
+            let mut machine = Machine::new(&instructions)
+                .with_optimizer()
+                .with_backend(backend);
+            machine
+                .restore(&Snapshot::from_json(&saved).unwrap())
+                .unwrap();
+            assert_eq!(machine.ip(), 9);
+            assert_eq!(machine[Reg::B], 1);
+            machine.run();
+            assert_eq!(machine.registers(), expected.registers());
+            assert_eq!(machine.instructions(), expected.instructions());
+        }
+    }
+
+    #[test]
+    fn test_program_registers() {
+        let source = "cpy 5 total\ninc total";
+        let unrelated = parse("inc unrelated", Dialect::Basic).unwrap();
+        let instructions = parse(source, Dialect::Basic).unwrap();
+        let mut machine = Machine::new(&instructions);
+        machine.run();
+        let snapshot = machine.snapshot();
+        let names = snapshot
+            .registers
+            .iter()
+            .map(|(reg, _)| reg.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "c", "d", "total"]);
+
+        // Restoring into a program parsed from scratch
+        let saved = snapshot.to_json();
+        let fresh = parse(source, Dialect::Basic).unwrap();
+        let mut restored = Machine::new(&fresh);
+        restored
+            .restore(&Snapshot::from_json(&saved).unwrap())
+            .unwrap();
+        assert_eq!(restored["total".parse().unwrap()], 6);
+
+        let other = Machine::new(&unrelated).snapshot();
+        assert!(matches!(
+            restored.restore(&other),
+            Err(SnapshotError::UnknownRegister(reg)) if reg.name() == "unrelated"
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let mut machine = Machine::new(&instructions);
+        let snapshot = machine.snapshot();
+
+        let other = parse("inc a", Dialect::Basic).unwrap();
+        let mut other = Machine::new(&other);
+        assert!(matches!(
+            other.restore(&snapshot),
+            Err(SnapshotError::ProgramMismatch)
+        ));
+
+        let mut swapped = snapshot.clone();
+        swapped.instructions.swap(0, 1);
+        assert!(matches!(
+            machine.restore(&swapped),
+            Err(SnapshotError::ProgramMismatch)
+        ));
+
+        for (ip, stopped) in [(instructions.len(), false), (instructions.len() + 1, true)] {
+            let mut past_end = snapshot.clone();
+            past_end.ip = ip;
+            past_end.stopped = stopped;
+            assert!(matches!(
+                machine.restore(&past_end),
+                Err(SnapshotError::InvalidIp(invalid)) if invalid == ip
+            ));
+        }
+        let mut stopped = snapshot.clone();
+        stopped.ip = instructions.len();
+        stopped.stopped = true;
+        machine.restore(&stopped).unwrap();
+        machine.run();
+        assert!(machine.is_stopped());
+
+        let mut extra = snapshot;
+        extra.registers.push(("e".parse().unwrap(), 1));
+        assert!(matches!(
+            machine.restore(&extra),
+            Err(SnapshotError::UnknownRegister(_))
+        ));
+
+        assert!(matches!(
+            Snapshot::from_json("{\"ip\":0}"),
+            Err(SnapshotError::MissingField(field)) if field == "stopped"
+        ));
+        assert!(matches!(
+            Snapshot::from_json("[1, 2"),
+            Err(SnapshotError::Json)
+        ));
+    }
+}
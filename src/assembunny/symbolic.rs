@@ -0,0 +1,914 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::ops::{Index, IndexMut};
+
+use thiserror::Error;
+
+use super::{Instruction, Op, Reg, RegOrValue, Registers, optimize};
+
+/// Give up after executing this many steps without reaching the end of the program.
+const MAX_STEPS: usize = 1 << 20;
+
+/// Falling factorials with at most this many factors are expanded into polynomials.
+const MAX_EXPANDED_FACTORS: i64 = 16;
+
+/// A factor of a term that is not a plain number.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Atom {
+    /// The value the register had at the start.
+    Reg(Reg),
+    Factorial(Box<Expr>),
+    /// `x * (x - 1) * ... * (x - n + 1)`
+    Falling(Box<Expr>, Box<Expr>),
+    /// `x` if the condition is not zero, and `y` otherwise.
+    IfNonZero(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Display for Atom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Reg(reg) => write!(f, "{reg}"),
+            Self::Factorial(x) if x.as_atom().is_some() => write!(f, "{x}!"),
+            Self::Factorial(x) => write!(f, "({x})!"),
+            Self::Falling(x, n) => write!(f, "falling({x}, {n})"),
+            Self::IfNonZero(condition, x, y) => write!(f, "if({condition}, {x}, {y})"),
+        }
+    }
+}
+
+/// Atoms multiplied together, with their exponents.
+type Monomial = BTreeMap<Atom, u32>;
+
+/// A polynomial in the initial register values, which may contain factorials of them.
+///
+/// Arithmetic returns `None` when a coefficient overflows.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Expr {
+    /// The coefficient of each monomial. Zero coefficients are left out, and the constant term
+    /// has an empty monomial.
+    terms: BTreeMap<Monomial, i64>,
+}
+
+impl Expr {
+    #[must_use]
+    pub fn constant(value: i64) -> Self {
+        Self::term(Monomial::new(), value)
+    }
+
+    /// The value the register had at the start.
+    #[must_use]
+    pub fn reg(reg: Reg) -> Self {
+        Self::atom(Atom::Reg(reg))
+    }
+
+    fn atom(atom: Atom) -> Self {
+        Self::term(Monomial::from([(atom, 1)]), 1)
+    }
+
+    fn term(monomial: Monomial, coefficient: i64) -> Self {
+        let mut terms = BTreeMap::new();
+        if coefficient != 0 {
+            terms.insert(monomial, coefficient);
+        }
+        Self { terms }
+    }
+
+    #[must_use]
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.first_key_value() {
+            None => Some(0),
+            Some((monomial, &value)) if monomial.is_empty() && self.terms.len() == 1 => Some(value),
+            Some(_) => None,
+        }
+    }
+
+    /// The atom, if the expression is just one.
+    fn as_atom(&self) -> Option<&Atom> {
+        let (monomial, &coefficient) = self.terms.first_key_value()?;
+        match monomial.first_key_value()? {
+            (atom, 1) if self.terms.len() == 1 && monomial.len() == 1 && coefficient == 1 => {
+                Some(atom)
+            }
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let mut terms = self.terms.clone();
+        for (monomial, &coefficient) in &other.terms {
+            let sum = terms.get(monomial).unwrap_or(&0).checked_add(coefficient)?;
+            if sum == 0 {
+                terms.remove(monomial);
+            } else {
+                terms.insert(monomial.clone(), sum);
+            }
+        }
+        Some(Self { terms })
+    }
+
+    #[must_use]
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        self.checked_add(&other.checked_mul(&Self::constant(-1))?)
+    }
+
+    #[must_use]
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mut product = Self::default();
+        for (left, &a) in &self.terms {
+            for (right, &b) in &other.terms {
+                let mut monomial = left.clone();
+                for (atom, &exponent) in right {
+                    *monomial.entry(atom.clone()).or_default() += exponent;
+                }
+                product = product.checked_add(&Self::term(monomial, a.checked_mul(b)?))?;
+            }
+        }
+        product.fold_factorials()
+    }
+
+    /// Rewrites `(x + 1) * x!` as `(x + 1)!`.
+    fn fold_factorials(self) -> Option<Self> {
+        let mut folded = Self::default();
+        for (mut monomial, coefficient) in self.terms {
+            while let Some((x, next)) = monomial.keys().find_map(|atom| {
+                let Atom::Factorial(x) = atom else {
+                    return None;
+                };
+                let next = x.checked_add(&Self::constant(1))?.as_atom()?.clone();
+                monomial.contains_key(&next).then(|| (atom.clone(), next))
+            }) {
+                for atom in [x, next.clone()] {
+                    match monomial.get_mut(&atom) {
+                        Some(1) => {
+                            monomial.remove(&atom);
+                        }
+                        Some(exponent) => *exponent -= 1,
+                        None => unreachable!("Both factors are in the monomial"),
+                    }
+                }
+                *monomial
+                    .entry(Atom::Factorial(Box::new(Self::atom(next))))
+                    .or_default() += 1;
+            }
+            folded = folded.checked_add(&Self::term(monomial, coefficient))?;
+        }
+        Some(folded)
+    }
+
+    /// `x!`, if `x` is not a negative number.
+    fn factorial(x: Self) -> Option<Self> {
+        match x.as_constant() {
+            Some(x) if x < 0 => None,
+            Some(x) => (1..=x)
+                .try_fold(1_i64, i64::checked_mul)
+                .map(Self::constant),
+            None => Some(Self::atom(Atom::Factorial(Box::new(x)))),
+        }
+    }
+
+    /// The product of the `n` numbers counting down from `x`, if `n` is not a negative number.
+    fn falling(x: Self, n: Self) -> Option<Self> {
+        match n.as_constant() {
+            Some(n) if n < 0 => None,
+            Some(n) if n <= MAX_EXPANDED_FACTORS => (0..n)
+                .try_fold(Self::constant(1), |product, i| {
+                    product.checked_mul(&x.checked_sub(&Self::constant(i))?)
+                }),
+            _ if x == n => Self::factorial(x),
+            _ => Some(Self::atom(Atom::Falling(Box::new(x), Box::new(n)))),
+        }
+    }
+
+    /// `x` if `condition` is not zero, and `y` otherwise.
+    #[must_use]
+    pub fn if_nonzero(condition: &Self, x: &Self, y: &Self) -> Self {
+        match condition.as_constant() {
+            Some(0) => y.clone(),
+            Some(_) => x.clone(),
+            // Where the condition is zero, so is `x`
+            None if x == y || (x == condition && *y == Self::default()) => x.clone(),
+            None => Self::atom(Atom::IfNonZero(
+                Box::new(condition.clone()),
+                Box::new(x.clone()),
+                Box::new(y.clone()),
+            )),
+        }
+    }
+
+    /// Whether the value depends on the initial value of `reg`.
+    #[must_use]
+    pub fn depends_on(&self, reg: Reg) -> bool {
+        self.terms
+            .keys()
+            .flat_map(Monomial::keys)
+            .any(|atom| match atom {
+                Atom::Reg(other) => *other == reg,
+                Atom::Factorial(x) => x.depends_on(reg),
+                Atom::Falling(x, n) => x.depends_on(reg) || n.depends_on(reg),
+                Atom::IfNonZero(condition, x, y) => {
+                    condition.depends_on(reg) || x.depends_on(reg) || y.depends_on(reg)
+                }
+            })
+    }
+
+    /// Replaces the initial value of each register with the expression `value` gives for it.
+    #[must_use]
+    pub fn substitute(&self, value: &impl Fn(Reg) -> Self) -> Option<Self> {
+        self.map_atoms(&|atom| match atom {
+            Atom::Reg(reg) => Some(value(*reg)),
+            Atom::Factorial(x) => Self::factorial(x.substitute(value)?),
+            Atom::Falling(x, n) => Self::falling(x.substitute(value)?, n.substitute(value)?),
+            Atom::IfNonZero(condition, x, y) => Some(Self::if_nonzero(
+                &condition.substitute(value)?,
+                &x.substitute(value)?,
+                &y.substitute(value)?,
+            )),
+        })
+    }
+
+    /// Evaluates the expression for the initial register values `value` gives. Returns `None`
+    /// if it overflows, or takes the factorial of a negative number.
+    #[must_use]
+    pub fn evaluate(&self, value: impl Fn(Reg) -> i64) -> Option<i64> {
+        self.substitute(&|reg| Self::constant(value(reg)))?
+            .as_constant()
+    }
+
+    fn map_atoms(&self, f: &impl Fn(&Atom) -> Option<Self>) -> Option<Self> {
+        let mut result = Self::default();
+        for (monomial, &coefficient) in &self.terms {
+            let mut term = Self::constant(coefficient);
+            for (atom, &exponent) in monomial {
+                let value = f(atom)?;
+                for _ in 0..exponent {
+                    term = term.checked_mul(&value)?;
+                }
+            }
+            result = result.checked_add(&term)?;
+        }
+        Some(result)
+    }
+}
+
+impl From<i64> for Expr {
+    fn from(value: i64) -> Self {
+        Self::constant(value)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        // Highest degree first, so the constant term goes last
+        let mut terms = self.terms.iter().collect::<Vec<_>>();
+        terms.sort_by_key(|(monomial, _)| Reverse(monomial.values().sum::<u32>()));
+        for (i, (monomial, &coefficient)) in terms.into_iter().enumerate() {
+            match (i, coefficient < 0) {
+                (0, true) => write!(f, "-")?,
+                (0, false) => {}
+                (_, true) => write!(f, " - ")?,
+                (_, false) => write!(f, " + ")?,
+            }
+            let magnitude = coefficient.unsigned_abs();
+            if monomial.is_empty() {
+                write!(f, "{magnitude}")?;
+                continue;
+            }
+            if magnitude != 1 {
+                write!(f, "{magnitude}*")?;
+            }
+            for (j, (atom, &exponent)) in monomial.iter().enumerate() {
+                if j > 0 {
+                    write!(f, "*")?;
+                }
+                write!(f, "{atom}")?;
+                if exponent > 1 {
+                    write!(f, "^{exponent}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Expr({self})")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SymbolicError {
+    #[error("The jump at instruction {0} depends on the initial registers")]
+    Branch(usize),
+    #[error("The loop starting at instruction {0} can not be summarized")]
+    Loop(usize),
+    #[error("The instruction toggled by instruction {0} depends on the initial registers")]
+    Toggle(usize),
+    #[error("Instruction {0} reads input")]
+    Input(usize),
+    #[error("Instruction {0} overflows, or takes the factorial of a negative number")]
+    Arithmetic(usize),
+    #[error("The program did not stop")]
+    StepLimit,
+}
+
+/// Runs a program with registers holding [`Expr`]s of their initial values, to find closed
+/// forms of what it computes.
+///
+/// Addition and multiplication loops are fused, and other counted loops are summarized where
+/// they start, by solving the recurrence each register follows over one pass through the body.
+/// Registers that are reset, count steadily, or are multiplied by a counting register are
+/// understood. Loops whose counter is known are run normally if they can not be summarized.
+///
+/// Jumps forward that depend on the initial registers are followed both ways until the two
+/// paths meet, and registers that differ are merged with [`Expr::if_nonzero`]. Loops that exit
+/// depending on the initial registers have to be summarized, as their states are not merged.
+///
+/// The results assume that every loop counts towards zero, as it must if the program stops.
+#[derive(Debug, Clone)]
+pub struct SymbolicMachine<'a> {
+    instructions: Cow<'a, [Instruction]>,
+    ops: Vec<Op>,
+    /// Loop heads, with the one `jnz` that jumps back to them.
+    loops: HashMap<usize, usize>,
+    ip: usize,
+    regs: Registers,
+    /// Indexed by position in `regs`.
+    registers: Vec<Expr>,
+    outputs: Vec<Expr>,
+    /// The head and back edge of the loop being summarized.
+    body: Option<(usize, usize)>,
+}
+
+impl<'a> SymbolicMachine<'a> {
+    /// Every register starts out as a symbol for its initial value.
+    #[must_use]
+    pub fn new(program: &'a [Instruction]) -> Self {
+        let regs = Registers::of(program);
+        Self {
+            ops: optimize(program),
+            loops: find_loops(program),
+            instructions: Cow::Borrowed(program),
+            ip: 0,
+            registers: regs.iter().copied().map(Expr::reg).collect(),
+            regs,
+            outputs: Vec::new(),
+            body: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn ip(&self) -> usize {
+        self.ip
+    }
+
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.ip >= self.instructions.len()
+    }
+
+    /// The register values, in the order of [`Registers::of`].
+    #[must_use]
+    pub fn registers(&self) -> &[Expr] {
+        &self.registers
+    }
+
+    #[must_use]
+    pub fn outputs(&self) -> &[Expr] {
+        &self.outputs
+    }
+
+    /// Runs the program to the end.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for the first step that can not be executed symbolically, or if the
+    /// program runs for too long.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..MAX_STEPS {
+            if self.is_stopped() {
+                return Ok(());
+            }
+            self.step()?;
+        }
+        Err(SymbolicError::StepLimit)
+    }
+
+    /// Executes one instruction, fused loop or summarized loop.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the step depends on the initial registers in a way that can not be
+    /// expressed, leaving the machine unchanged.
+    pub fn step(&mut self) -> Result<(), SymbolicError> {
+        let ip = self.ip;
+        if self.fused(self.ops[ip])? {
+            return Ok(());
+        }
+        if let Some(&end) = self.loops.get(&ip)
+            && self.body.is_none_or(|(head, _)| head != ip)
+        {
+            match self.summarize(ip, end) {
+                Ok(registers) => {
+                    self.registers = registers;
+                    self.ip = end + 1;
+                    return Ok(());
+                }
+                Err(error) if self.value(self.condition(end)).as_constant().is_none() => {
+                    return Err(error);
+                }
+                // The counter is known, so the loop can be run normally
+                Err(_) => {}
+            }
+        }
+        let arithmetic = SymbolicError::Arithmetic(ip);
+        match self.instructions[ip] {
+            Instruction::Cpy(value, RegOrValue::Reg(reg)) => self[reg] = self.value(value),
+            Instruction::Inc(RegOrValue::Reg(reg)) => {
+                self[reg] = self[reg]
+                    .checked_add(&Expr::constant(1))
+                    .ok_or(arithmetic)?;
+            }
+            Instruction::Dec(RegOrValue::Reg(reg)) => {
+                self[reg] = self[reg]
+                    .checked_sub(&Expr::constant(1))
+                    .ok_or(arithmetic)?;
+            }
+            Instruction::Jnz(condition, offset) => match self.value(condition).as_constant() {
+                Some(0) => {}
+                Some(_) => {
+                    let offset = self.offset(offset).ok_or(SymbolicError::Branch(ip))?;
+                    self.ip = ip
+                        .checked_add_signed(offset)
+                        .filter(|&target| target < self.instructions.len())
+                        .unwrap_or(self.instructions.len());
+                    return Ok(());
+                }
+                None => return self.merge_branch(ip, condition, offset),
+            },
+            Instruction::Tgl(offset) => {
+                if let Some((head, _)) = self.body {
+                    return Err(SymbolicError::Loop(head));
+                }
+                let offset = self.offset(offset).ok_or(SymbolicError::Toggle(ip))?;
+                if let Some(target) = ip
+                    .checked_add_signed(offset)
+                    .filter(|&target| target < self.instructions.len())
+                {
+                    let instructions = self.instructions.to_mut();
+                    instructions[target] = instructions[target].toggle();
+                    self.ops = optimize(instructions);
+                    self.loops = find_loops(instructions);
+                }
+            }
+            Instruction::Out(value) => {
+                if let Some((head, _)) = self.body {
+                    return Err(SymbolicError::Loop(head));
+                }
+                self.outputs.push(self.value(value));
+            }
+            Instruction::In(_) => return Err(SymbolicError::Input(ip)),
+            // Toggling can produce invalid instructions, which are skipped
+            Instruction::Cpy(..) | Instruction::Inc(..) | Instruction::Dec(..) => {}
+        }
+        self.ip += 1;
+        Ok(())
+    }
+
+    fn value(&self, value: RegOrValue) -> Expr {
+        match value {
+            RegOrValue::Reg(reg) => self[reg].clone(),
+            RegOrValue::Value(value) => Expr::constant(value),
+        }
+    }
+
+    /// A jump or toggle offset, if it is known.
+    fn offset(&self, offset: RegOrValue) -> Option<isize> {
+        isize::try_from(self.value(offset).as_constant()?).ok()
+    }
+
+    fn condition(&self, ip: usize) -> RegOrValue {
+        match self.instructions[ip] {
+            Instruction::Jnz(condition, _) => condition,
+            _ => unreachable!("Loops end with a jnz"),
+        }
+    }
+
+    /// Follows both sides of the jump at `ip` until they reach the same instruction, and merges
+    /// them. Both sides have to stay after the jump, and inside the loop body being summarized.
+    fn merge_branch(
+        &mut self,
+        ip: usize,
+        condition: RegOrValue,
+        offset: RegOrValue,
+    ) -> Result<(), SymbolicError> {
+        let error = SymbolicError::Branch(ip);
+        let offset = self
+            .offset(offset)
+            .filter(|&offset| offset > 0)
+            .ok_or(error)?;
+        let len = self.instructions.len();
+        let end = self.body.map_or(len, |(_, end)| end);
+        let mut taken = self.clone();
+        taken.ip = ip.saturating_add_signed(offset).min(len);
+        let mut skipped = self.clone();
+        skipped.ip = ip + 1;
+        if let RegOrValue::Reg(reg) = condition {
+            skipped[reg] = Expr::default();
+        }
+        for _ in 0..MAX_STEPS {
+            if taken.ip == skipped.ip {
+                break;
+            }
+            // Step whichever side is behind, so they meet at the first instruction both reach
+            let behind = if taken.ip < skipped.ip {
+                &mut taken
+            } else {
+                &mut skipped
+            };
+            if behind.ip <= ip || behind.ip >= end {
+                return Err(error);
+            }
+            behind.step()?;
+        }
+        if taken.ip != skipped.ip
+            || taken.instructions != skipped.instructions
+            || taken.outputs.len() != skipped.outputs.len()
+        {
+            return Err(error);
+        }
+        let condition = self.value(condition);
+        let merge = |x: &mut Vec<Expr>, y: &[Expr]| {
+            for (x, y) in x.iter_mut().zip(y) {
+                *x = Expr::if_nonzero(&condition, x, y);
+            }
+        };
+        merge(&mut taken.registers, &skipped.registers);
+        merge(&mut taken.outputs, &skipped.outputs);
+        *self = taken;
+        Ok(())
+    }
+
+    /// Executes a fused loop, returning `false` if it is not known to stop normally.
+    fn fused(&mut self, op: Op) -> Result<bool, SymbolicError> {
+        let arithmetic = SymbolicError::Arithmetic(self.ip);
+        let iterations = |counter: Expr, step: i64| {
+            let count = counter.checked_mul(&Expr::constant(-step))?;
+            count
+                .as_constant()
+                .is_none_or(|count| count > 0)
+                .then_some(count)
+        };
+        match op {
+            Op::Plain(_) => return Ok(false),
+            Op::Add {
+                target,
+                target_step,
+                counter,
+                counter_step,
+            } => {
+                let Some(count) = iterations(self[counter].clone(), counter_step) else {
+                    return Ok(false);
+                };
+                self[target] = count
+                    .checked_mul(&Expr::constant(target_step))
+                    .and_then(|delta| self[target].checked_add(&delta))
+                    .ok_or(arithmetic)?;
+                self[counter] = Expr::default();
+            }
+            Op::Mul {
+                source,
+                target,
+                target_step,
+                inner,
+                inner_step,
+                outer,
+                outer_step,
+            } => {
+                let (Some(inner_count), Some(outer_count)) = (
+                    iterations(self.value(source), inner_step),
+                    iterations(self[outer].clone(), outer_step),
+                ) else {
+                    return Ok(false);
+                };
+                self[target] = inner_count
+                    .checked_mul(&outer_count)
+                    .and_then(|count| count.checked_mul(&Expr::constant(target_step)))
+                    .and_then(|delta| self[target].checked_add(&delta))
+                    .ok_or(arithmetic)?;
+                self[inner] = Expr::default();
+                self[outer] = Expr::default();
+            }
+        }
+        self.ip += op.span();
+        Ok(true)
+    }
+
+    /// The registers after running the loop from `head` to the `jnz` at `end` until it stops.
+    fn summarize(&self, head: usize, end: usize) -> Result<Vec<Expr>, SymbolicError> {
+        let error = SymbolicError::Loop(head);
+        let RegOrValue::Reg(counter) = self.condition(end) else {
+            return Err(error);
+        };
+        let regs = self.regs.iter().copied();
+
+        // One pass through the body, in terms of the registers at the start of the pass
+        let mut body = Self {
+            instructions: self.instructions.clone(),
+            ops: self.ops.clone(),
+            loops: self.loops.clone(),
+            ip: head,
+            regs: self.regs.clone(),
+            registers: regs.clone().map(Expr::reg).collect(),
+            outputs: Vec::new(),
+            body: Some((head, end)),
+        };
+        for _ in 0..MAX_STEPS {
+            if body.ip == end {
+                break;
+            }
+            if !(head..end).contains(&body.ip) {
+                return Err(error);
+            }
+            body.step()?;
+        }
+        if body.ip != end {
+            return Err(SymbolicError::StepLimit);
+        }
+        let pass = |reg| &body.registers[self.regs.slot(reg)];
+
+        let modified = regs
+            .filter(|&reg| *pass(reg) != Expr::reg(reg))
+            .collect::<Vec<_>>();
+        let is_invariant = |expr: &Expr| !modified.iter().any(|&reg| expr.depends_on(reg));
+        let step = |reg: Reg| {
+            pass(reg)
+                .checked_sub(&Expr::reg(reg))?
+                .as_constant()
+                .filter(|step| step.abs() == 1)
+        };
+        let count = step(counter)
+            .and_then(|step| self[counter].checked_mul(&Expr::constant(-step)))
+            .filter(|count| count.as_constant().is_none_or(|count| count > 0))
+            .ok_or(error)?;
+
+        let arithmetic = SymbolicError::Arithmetic(head);
+        let mut registers = self.registers.clone();
+        for &reg in &modified {
+            let pass = pass(reg);
+            let start = &self[reg];
+            let delta = pass.checked_sub(&Expr::reg(reg)).ok_or(arithmetic)?;
+            registers[self.regs.slot(reg)] = if is_invariant(pass) {
+                // Reset to the same value on every pass
+                pass.substitute(&|reg| self[reg].clone())
+            } else if is_invariant(&delta) {
+                delta
+                    .substitute(&|reg| self[reg].clone())
+                    .and_then(|delta| delta.checked_mul(&count))
+                    .and_then(|delta| start.checked_add(&delta))
+            } else if let Some((factor, step)) = modified.iter().find_map(|&factor| {
+                let product = Expr::reg(reg).checked_mul(&Expr::reg(factor))?;
+                (factor != reg && *pass == product).then_some((factor, step(factor)?))
+            }) {
+                // Multiplied by a counting register, whose first value is the largest factor
+                // when counting down, and the last one when counting up
+                let first = &self[factor];
+                let largest = if step < 0 {
+                    Some(first.clone())
+                } else {
+                    first
+                        .checked_add(&count)
+                        .and_then(|last| last.checked_sub(&Expr::constant(1)))
+                };
+                largest
+                    .and_then(|largest| Expr::falling(largest, count.clone()))
+                    .and_then(|product| start.checked_mul(&product))
+            } else {
+                return Err(error);
+            }
+            .ok_or(arithmetic)?;
+        }
+        Ok(registers)
+    }
+}
+
+/// Finds the loops that are closed by a single `jnz` with a register condition.
+fn find_loops(instructions: &[Instruction]) -> HashMap<usize, usize> {
+    let mut back_edges = HashMap::<usize, Vec<usize>>::new();
+    for (ip, instruction) in instructions.iter().enumerate() {
+        if let Instruction::Jnz(_, RegOrValue::Value(offset @ ..0)) = *instruction
+            && let Some(head) = isize::try_from(offset)
+                .ok()
+                .and_then(|offset| ip.checked_add_signed(offset))
+        {
+            back_edges.entry(head).or_default().push(ip);
+        }
+    }
+    back_edges
+        .into_iter()
+        .filter_map(|(head, ends)| match *ends {
+            [end] if matches!(instructions[end], Instruction::Jnz(RegOrValue::Reg(_), _)) => {
+                Some((head, end))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+impl Index<Reg> for SymbolicMachine<'_> {
+    type Output = Expr;
+
+    fn index(&self, index: Reg) -> &Self::Output {
+        &self.registers[self.regs.slot(index)]
+    }
+}
+
+impl IndexMut<Reg> for SymbolicMachine<'_> {
+    fn index_mut(&mut self, index: Reg) -> &mut Self::Output {
+        &mut self.registers[self.regs.slot(index)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, Machine, parse};
+
+    /// Computes `a! + 77 * 73`, like day 23 does once its toggles have settled.
+    const FACTORIAL: &str = "\
+        cpy a b\n\
+        dec b\n\
+        cpy a d\n\
+        cpy 0 a\n\
+        cpy b c\n\
+        inc a\n\
+        dec c\n\
+        jnz c -2\n\
+        dec d\n\
+        jnz d -5\n\
+        dec b\n\
+        jnz b -9\n\
+        cpy 77 c\n\
+        cpy 73 d\n\
+        inc a\n\
+        dec d\n\
+        jnz d -2\n\
+        dec c\n\
+        jnz c -5\
+    ";
+
+    #[test]
+    fn test_factorial() {
+        let instructions = parse(FACTORIAL, Dialect::Basic).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        let formula = &symbolic[Reg::A];
+        assert_eq!(formula.to_string(), "a! + 5621");
+        assert_eq!(symbolic[Reg::B], Expr::constant(0));
+
+        for a in 2..=12 {
+            let mut machine = Machine::new(&instructions).with_optimizer();
+            machine[Reg::A] = a;
+            let initial = machine.clone();
+            machine.run();
+            assert_eq!(formula.evaluate(|reg| initial[reg]), Some(machine[Reg::A]));
+        }
+        assert_eq!(
+            formula.evaluate(|reg| if reg == Reg::A { 21 } else { 0 }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_recurrences() {
+        // c counts down from a, then up from -3 while b is reset and d grows by two
+        let source = "\
+            cpy a c\n\
+            cpy 0 d\n\
+            cpy 1 a\n\
+            dec c\n\
+            jnz c -1\n\
+            cpy 0 c\n\
+            dec c\n\
+            dec c\n\
+            dec c\n\
+            cpy 2 b\n\
+            inc d\n\
+            inc d\n\
+            inc a\n\
+            dec a\n\
+            inc c\n\
+            jnz c -6\
+        ";
+        let instructions = parse(source, Dialect::Basic).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic[Reg::B], Expr::constant(2));
+        assert_eq!(symbolic[Reg::C], Expr::constant(0));
+        assert_eq!(symbolic[Reg::D], Expr::constant(6));
+
+        let instructions = parse("inc c\ndec a\njnz a -2", Dialect::Basic).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic[Reg::C].to_string(), "a + c");
+    }
+
+    #[test]
+    fn test_products() {
+        // b = a * (a - 1) * (a - 2), by multiplying with a counter that counts down three times
+        let source = "\
+            cpy a d\n\
+            cpy 1 b\n\
+            cpy 3 c\n\
+            cpy b e\n\
+            cpy 0 b\n\
+            cpy d f\n\
+            inc b\n\
+            dec f\n\
+            jnz f -2\n\
+            dec e\n\
+            jnz e -5\n\
+            dec d\n\
+            dec c\n\
+            jnz c -10\
+        ";
+        let instructions = parse(source, Dialect::Basic).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic[Reg::B].to_string(), "a^3 - 3*a^2 + 2*a");
+    }
+
+    #[test]
+    fn test_branches() {
+        // b is 7 if a is not zero, and 5 otherwise, and c is incremented either way
+        let source = "\
+            cpy 0 c\n\
+            jnz a 3\n\
+            cpy 5 b\n\
+            jnz 1 2\n\
+            cpy 7 b\n\
+            inc c\n\
+            out b\
+        ";
+        let instructions = parse(source, Dialect::Clock).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic[Reg::A], Expr::reg(Reg::A));
+        assert_eq!(symbolic[Reg::B].to_string(), "if(a, 7, 5)");
+        assert_eq!(symbolic[Reg::C], Expr::constant(1));
+        assert_eq!(symbolic.outputs(), [symbolic[Reg::B].clone()]);
+
+        for a in -2..=2 {
+            let mut machine = Machine::new(&instructions);
+            machine[Reg::A] = a;
+            machine.run();
+            let value = |reg| if reg == Reg::A { a } else { 0 };
+            assert_eq!(symbolic[Reg::B].evaluate(value), Some(machine[Reg::B]));
+        }
+
+        // Sides that output different amounts can not be merged
+        let instructions = parse("jnz a 2\nout a\ninc b", Dialect::Clock).unwrap();
+        assert_eq!(
+            SymbolicMachine::new(&instructions).run(),
+            Err(SymbolicError::Branch(0))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        // Jumping back depending on a, from outside of any loop
+        let instructions = parse("inc b\njnz a -1\njnz c -2", Dialect::Basic).unwrap();
+        assert_eq!(
+            SymbolicMachine::new(&instructions).run(),
+            Err(SymbolicError::Branch(1))
+        );
+        // d doubles on every pass, which is not a supported recurrence
+        let instructions = parse(
+            "cpy d b\ninc d\ndec b\njnz b -2\ndec a\njnz a -5",
+            Dialect::Basic,
+        )
+        .unwrap();
+        assert_eq!(
+            SymbolicMachine::new(&instructions).run(),
+            Err(SymbolicError::Loop(0))
+        );
+        let instructions = parse("tgl a", Dialect::Toggle).unwrap();
+        assert_eq!(
+            SymbolicMachine::new(&instructions).run(),
+            Err(SymbolicError::Toggle(0))
+        );
+
+        // Known counters run normally, even through toggles
+        let instructions =
+            parse("cpy 3 c\ntgl 5\ninc a\ndec c\njnz c -3", Dialect::Toggle).unwrap();
+        let mut symbolic = SymbolicMachine::new(&instructions);
+        symbolic.run().unwrap();
+        assert_eq!(symbolic[Reg::A].to_string(), "a + 3");
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+use super::json::{Json, JsonError, JsonParser};
+use super::{Instruction, Machine, Op, ParseError};
+
+/// Writes one JSON record per step to a writer.
+///
+/// ```text
+/// {"step":1,"ip":0,"instruction":"cpy 2 a","registers":[2,0,0,0],"toggled":null}
+/// {"step":2,"ip":1,"instruction":"tgl a","registers":[2,0,0,0],"toggled":{"ip":3,"instruction":"inc a"}}
+/// ```
+///
+/// A fused loop is one step, with the fused operation as its instruction. The first write error
+/// is kept, and stops the tracing.
+#[derive(Clone)]
+pub struct Tracer {
+    writer: Arc<Mutex<dyn Write + Send>>,
+    pub(super) steps: u64,
+    error: Option<Arc<io::Error>>,
+}
+
+impl Tracer {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            steps: 0,
+            error: None,
+        }
+    }
+
+    fn write(&mut self, record: &TraceRecord) {
+        if self.error.is_some() {
+            return;
+        }
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        if let Err(err) = writeln!(writer, "{}", record.to_json()) {
+            self.error = Some(Arc::new(err));
+        }
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("steps", &self.steps)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Machine<'_> {
+    /// Record every step. Traced machines are always interpreted.
+    #[must_use]
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// The error that stopped the tracing, if any.
+    #[must_use]
+    pub fn trace_error(&self) -> Option<&io::Error> {
+        self.tracer.as_ref()?.error.as_deref()
+    }
+
+    /// Records a step that executed `op` at `ip`, which toggled the instruction at `toggled`.
+    pub(super) fn trace(&mut self, ip: usize, op: Op, toggled: Option<usize>) {
+        let toggled = toggled.map(|target| (target, self.instructions[target]));
+        let Some(tracer) = &mut self.tracer else {
+            return;
+        };
+        tracer.steps += 1;
+        tracer.write(&TraceRecord {
+            step: tracer.steps,
+            ip,
+            instruction: op.to_string(),
+            registers: self.registers.clone(),
+            toggled,
+        });
+    }
+}
+
+/// One step of a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Counted from 1.
+    pub step: u64,
+    pub ip: usize,
+    /// The instruction or fused operation that was executed.
+    pub instruction: String,
+    /// The registers after the step, in the order of [`Machine::regs`].
+    pub registers: Vec<i64>,
+    /// The index and new value of an instruction changed by `tgl`.
+    pub toggled: Option<(usize, Instruction)>,
+}
+
+impl TraceRecord {
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let registers = self
+            .registers
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let toggled = match self.toggled {
+            Some((ip, instruction)) => format!("{{\"ip\":{ip},\"instruction\":\"{instruction}\"}}"),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"step\":{},\"ip\":{},\"instruction\":\"{}\",\"registers\":[{registers}],\"toggled\":{toggled}}}",
+            self.step, self.ip, self.instruction
+        )
+    }
+
+    /// Parses a record written by [`TraceRecord::to_json`]. Fields may come in any order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the line is not a JSON object with the fields of a record.
+    pub fn from_json(line: &str) -> Result<Self, TraceError> {
+        let value = JsonParser::parse(line)?;
+        let step = value.get("step")?.number()?;
+        let ip = value.get("ip")?.number()?;
+        let instruction = value.get("instruction")?.string()?.to_string();
+        let registers = value
+            .get("registers")?
+            .array()?
+            .iter()
+            .map(Json::number)
+            .collect::<Result<_, _>>()?;
+        let toggled = match value.get("toggled")? {
+            Json::Null => None,
+            toggled => Some((
+                toggled.get("ip")?.number()?,
+                toggled.get("instruction")?.string()?.parse()?,
+            )),
+        };
+        Ok(Self {
+            step,
+            ip,
+            instruction,
+            registers,
+            toggled,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error("Invalid trace record")]
+    Json,
+    #[error("Trace record has no field {0:?}")]
+    MissingField(String),
+    #[error(transparent)]
+    Instruction(#[from] ParseError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl From<JsonError> for TraceError {
+    fn from(error: JsonError) -> Self {
+        match error {
+            JsonError::Invalid => Self::Json,
+            JsonError::MissingField(field) => Self::MissingField(field),
+        }
+    }
+}
+
+/// Reads a trace written by a [`Tracer`].
+///
+/// # Errors
+///
+/// Returns an error if reading fails, or a line is not a valid record.
+pub fn read_trace(reader: impl BufRead) -> Result<Vec<TraceRecord>, TraceError> {
+    reader
+        .lines()
+        .filter(|line| {
+            line.as_ref()
+                .is_ok_and(|line| !line.trim_ascii().is_empty())
+        })
+        .map(|line| TraceRecord::from_json(&line?))
+        .collect()
+}
+
+/// The first records of two traces that disagree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Finds where two runs of the same program start to differ.
+///
+/// The runs are compared by the state after each step. Since a fused loop skips over the states
+/// inside it, a state that is missing from one run is skipped in the other, so an optimized run
+/// agrees with an interpreted run of the same program. Runs that end in different states diverge
+/// at the end of the shorter one.
+#[must_use]
+pub fn diff(left: &[TraceRecord], right: &[TraceRecord]) -> Option<Divergence> {
+    let left_states = states(left);
+    let right_states = states(right);
+    let (mut i, mut j) = (0, 0);
+    while i < left.len() && j < right.len() {
+        if left_states[i] == right_states[j] {
+            i += 1;
+            j += 1;
+        } else if let Some(k) = right_states[j..].iter().position(|s| *s == left_states[i]) {
+            j += k;
+        } else if let Some(k) = left_states[i..].iter().position(|s| *s == right_states[j]) {
+            i += k;
+        } else {
+            return Some(Divergence { left: i, right: j });
+        }
+    }
+    (i < left.len() || j < right.len()).then_some(Divergence { left: i, right: j })
+}
+
+/// The instruction pointer and registers after each step. The instruction pointer after the
+/// last step is unknown.
+fn states(records: &[TraceRecord]) -> Vec<(Option<usize>, &[i64])> {
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            (
+                records.get(i + 1).map(|next| next.ip),
+                &record.registers[..],
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, Reg, RegOrValue, Registers, parse};
+
+    /// A `Write` that can be read after the machine is done with it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Day 23 example, with a multiplication loop added.
+    const PROGRAM: &str = "\
+        cpy 2 a\n\
+        tgl a\n\
+        tgl a\n\
+        tgl a\n\
+        cpy 1 a\n\
+        dec a\n\
+        dec a\n\
+        cpy 3 d\n\
+        cpy 4 c\n\
+        inc b\n\
+        dec c\n\
+        jnz c -2\n\
+        dec d\n\
+        jnz d -5\
+    ";
+
+    fn trace(machine: Machine) -> Vec<TraceRecord> {
+        let buffer = Shared::default();
+        let mut machine = machine.with_tracer(Tracer::new(buffer.clone()));
+        machine.run();
+        assert!(machine.trace_error().is_none());
+        let bytes = buffer.0.lock().unwrap().clone();
+        read_trace(bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_records() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let records = trace(Machine::new(&instructions));
+        assert_eq!(
+            records[1].to_json(),
+            "{\"step\":2,\"ip\":1,\"instruction\":\"tgl a\",\"registers\":[2,0,0,0],\
+            \"toggled\":{\"ip\":3,\"instruction\":\"inc a\"}}"
+        );
+        assert_eq!(
+            records[1].toggled,
+            Some((3, Instruction::Inc(RegOrValue::Reg(Reg::A))))
+        );
+        assert_eq!(records.last().unwrap().registers, [3, 12, 0, 0]);
+        for record in &records {
+            assert_eq!(TraceRecord::from_json(&record.to_json()).unwrap(), *record);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let instructions = parse(PROGRAM, Dialect::Toggle).unwrap();
+        let interpreted = trace(Machine::new(&instructions));
+        let optimized = trace(Machine::new(&instructions).with_optimizer());
+        assert!(optimized.len() < interpreted.len());
+        assert!(optimized.last().unwrap().instruction.starts_with("mul"));
+        assert_eq!(diff(&interpreted, &optimized), None);
+        assert_eq!(diff(&optimized, &interpreted), None);
+
+        let mut changed = optimized.clone();
+        let b = Registers::of(&instructions).index(Reg::B).unwrap();
+        changed[4].registers[b] = 7;
+        assert_eq!(
+            diff(&interpreted, &changed),
+            Some(Divergence { left: 4, right: 4 })
+        );
+        assert_eq!(
+            diff(&interpreted, &optimized[..5]),
+            Some(Divergence { left: 4, right: 4 })
+        );
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        assert!(matches!(
+            TraceRecord::from_json("{\"step\":1}"),
+            Err(TraceError::MissingField(field)) if field == "ip"
+        ));
+        assert!(matches!(
+            TraceRecord::from_json("{\"step\":1"),
+            Err(TraceError::Json)
+        ));
+    }
+}
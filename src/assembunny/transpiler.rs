@@ -0,0 +1,494 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use super::cfg::{ControlFlowGraph, Target, jump_at};
+use super::{Instruction, RegOrValue, Registers};
+
+/// What [`transpile`] makes of the programs in [`tests::FIXTURES`], checked in so that the tests
+/// can run it.
+#[cfg(test)]
+#[rustfmt::skip]
+#[allow(clippy::all, clippy::pedantic, clippy::nursery, reason = "Generated code")]
+mod fixture;
+
+#[derive(Debug, Error)]
+pub enum TranspileError {
+    #[error("Instruction {0} may toggle another instruction")]
+    Toggle(usize),
+    #[error("Register {0:?} can not be used as a Rust variable name")]
+    RegisterName(String),
+}
+
+/// Names that would clash with Rust keywords or the variables of the generated code.
+const RESERVED_NAMES: &[&str] = &[
+    "abstract",
+    "as",
+    "async",
+    "await",
+    "become",
+    "box",
+    "break",
+    "const",
+    "continue",
+    "crate",
+    "do",
+    "dyn",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "final",
+    "fn",
+    "for",
+    "gen",
+    "if",
+    "impl",
+    "in",
+    "input",
+    "ip",
+    "let",
+    "loop",
+    "macro",
+    "match",
+    "mod",
+    "move",
+    "mut",
+    "out",
+    "override",
+    "priv",
+    "pub",
+    "ref",
+    "registers",
+    "return",
+    "self",
+    "static",
+    "struct",
+    "super",
+    "trait",
+    "true",
+    "try",
+    "type",
+    "typeof",
+    "unsafe",
+    "unsized",
+    "use",
+    "value",
+    "virtual",
+    "where",
+    "while",
+    "yield",
+];
+
+/// Generates a self-contained Rust function named `name` that runs the program.
+///
+/// The function takes the initial registers, in the order of [`Registers::of`], and returns the
+/// final ones. Registers become local variables of the same name. Programs that use `in` take a
+/// callback that supplies the values, and return when it runs out. Programs that use `out`
+/// take a callback that receives each value and returns whether to keep running. Basic
+/// blocks become the arms of a `match` on the instruction pointer, and blocks that jump back to
+/// themselves become plain loops, which the Rust compiler optimizes well.
+///
+/// Arithmetic is checked like [`Arithmetic::Checked`](super::Arithmetic::Checked): the function
+/// returns as soon as a register would overflow, with the registers the machine stops with.
+///
+/// # Errors
+///
+/// Programs with a `tgl` that might hit an instruction can not be compiled ahead of time, and
+/// registers named like a Rust keyword can not be variables.
+pub fn transpile(instructions: &[Instruction], name: &str) -> Result<String, TranspileError> {
+    check(instructions)?;
+    let len = instructions.len();
+    // Jumps with register offsets can land anywhere
+    let starts = if (0..len)
+        .any(|ip| jump_at(instructions, ip).is_some_and(|jump| jump.target == Target::Unknown))
+    {
+        (0..len).collect()
+    } else {
+        ControlFlowGraph::new(instructions)
+            .blocks()
+            .iter()
+            .map(|block| block.start)
+            .collect::<Vec<_>>()
+    };
+    let uses = |f: fn(&Instruction) -> bool| instructions.iter().any(f);
+    let input = if uses(|instruction| matches!(instruction, Instruction::In(_))) {
+        ", mut input: impl FnMut() -> Option<i64>"
+    } else {
+        ""
+    };
+    let output = if uses(|instruction| matches!(instruction, Instruction::Out(_))) {
+        ", mut out: impl FnMut(i64) -> bool"
+    } else {
+        ""
+    };
+
+    let regs = Registers::of(instructions);
+    let size = regs.len();
+    let (locals, values) = variables(&regs);
+
+    let mut code = String::new();
+    code.push_str("#[allow(unused_mut)]\n");
+    let _ = writeln!(
+        code,
+        "pub fn {name}(registers: [i64; {size}]{input}{output}) -> [i64; {size}] {{"
+    );
+    let _ = writeln!(code, "    let [{locals}] = registers;");
+    code.push_str("    let mut ip = 0;\n");
+    code.push_str("    loop {\n");
+    code.push_str("        match ip {\n");
+    for (&start, end) in starts
+        .iter()
+        .zip(starts.iter().skip(1).copied().chain([len]))
+    {
+        let _ = writeln!(code, "            {start} => {{");
+        let jump = jump_at(instructions, end - 1);
+        let body = if jump.is_some() { end - 1 } else { end };
+        match jump {
+            Some(jump) if jump.target == Target::Ip(start) && !jump.is_unconditional() => {
+                code.push_str("                loop {\n");
+                write_body(&mut code, instructions, start..body, &values, 5);
+                let _ = writeln!(
+                    code,
+                    "                    if {} == 0 {{\n                        break;\n                    }}",
+                    jump.condition
+                );
+                code.push_str("                }\n");
+                let _ = writeln!(code, "                ip = {end};");
+            }
+            Some(jump) => {
+                write_body(&mut code, instructions, start..body, &values, 4);
+                let taken = match (jump.target, instructions[end - 1]) {
+                    (Target::Ip(target), _) => format!("ip = {target};"),
+                    (Target::Exit, _) => "break;".to_string(),
+                    (Target::Unknown, Instruction::Jnz(_, offset)) => format!(
+                        "ip = {}_i64.checked_add({offset}).and_then(|ip| usize::try_from(ip).ok()).unwrap_or(usize::MAX);",
+                        end - 1
+                    ),
+                    (Target::Unknown, _) => unreachable!("Only jnz jumps"),
+                };
+                if jump.is_unconditional() {
+                    let _ = writeln!(code, "                {taken}");
+                } else {
+                    let _ = writeln!(
+                        code,
+                        "                if {} != 0 {{\n                    {taken}\n                }} else {{\n                    ip = {end};\n                }}",
+                        jump.condition
+                    );
+                }
+            }
+            None => {
+                write_body(&mut code, instructions, start..body, &values, 4);
+                let _ = writeln!(code, "                ip = {end};");
+            }
+        }
+        code.push_str("            }\n");
+    }
+    code.push_str("            _ => break,\n");
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+    let _ = writeln!(code, "    {values}");
+    code.push_str("}\n");
+    Ok(code)
+}
+
+fn check(instructions: &[Instruction]) -> Result<(), TranspileError> {
+    if let Some(reg) = instructions
+        .iter()
+        .flat_map(|instruction| instruction.registers())
+        .find(|reg| RESERVED_NAMES.contains(&reg.name()))
+    {
+        return Err(TranspileError::RegisterName(reg.name().to_string()));
+    }
+    for (ip, &instruction) in instructions.iter().enumerate() {
+        if let Instruction::Tgl(offset) = instruction
+            && !matches!(offset, RegOrValue::Value(offset) if target(ip, offset, instructions.len()).is_none())
+        {
+            return Err(TranspileError::Toggle(ip));
+        }
+    }
+    Ok(())
+}
+
+/// The pattern that unpacks the registers into variables, and the expression that packs them up
+/// again.
+fn variables(regs: &Registers) -> (String, String) {
+    let locals = regs
+        .iter()
+        .map(|reg| format!("mut {reg}"))
+        .collect::<Vec<_>>();
+    let values = regs.iter().map(ToString::to_string).collect::<Vec<_>>();
+    (locals.join(", "), format!("[{}]", values.join(", ")))
+}
+
+/// The index `offset` instructions from `ip`, if it is inside the program.
+fn target(ip: usize, offset: i64, len: usize) -> Option<usize> {
+    isize::try_from(offset)
+        .ok()
+        .and_then(|offset| ip.checked_add_signed(offset))
+        .filter(|&target| target < len)
+}
+
+/// Writes the statements for instructions that do not jump.
+fn write_body(
+    code: &mut String,
+    instructions: &[Instruction],
+    range: std::ops::Range<usize>,
+    values: &str,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    for instruction in &instructions[range] {
+        let _ = match *instruction {
+            Instruction::Cpy(value, RegOrValue::Reg(reg)) => {
+                writeln!(code, "{indent}{reg} = {value};")
+            }
+            Instruction::Inc(RegOrValue::Reg(reg)) | Instruction::Dec(RegOrValue::Reg(reg)) => {
+                let method = if matches!(instruction, Instruction::Inc(_)) {
+                    "checked_add"
+                } else {
+                    "checked_sub"
+                };
+                writeln!(
+                    code,
+                    "{indent}let Some(value) = {reg}.{method}(1) else {{\n{indent}    return {values};\n{indent}}};\n{indent}{reg} = value;"
+                )
+            }
+            Instruction::Out(value) => writeln!(
+                code,
+                "{indent}if !out({value}) {{\n{indent}    return {values};\n{indent}}}"
+            ),
+            Instruction::In(RegOrValue::Reg(reg)) => writeln!(
+                code,
+                "{indent}let Some(value) = input() else {{\n{indent}    return {values};\n{indent}}};\n{indent}{reg} = value;"
+            ),
+            // Invalid instructions, `jnz 0` and toggles that miss the program do nothing
+            _ => writeln!(code, "{indent}// {instruction}"),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembunny::{Dialect, Machine, Reg, assemble};
+
+    /// Programs to run both transpiled and on a [`Machine`], by the name of their function.
+    pub(super) const FIXTURES: [(&str, &str, Dialect); 4] = [
+        (
+            "fibonacci",
+            "cpy 1 a\ncpy 1 b\ncpy 26 d\njnz c 2\njnz 1 5\ncpy 7 c\ninc d\ndec c\njnz c -2\n\
+             cpy a c\ninc a\ndec b\njnz b -2\ncpy c b\ndec d\njnz d -6",
+            Dialect::Basic,
+        ),
+        (
+            "dynamic_jumps",
+            "jnz 1 a\ninc b\ninc b\ninc c\ndec d\njnz d b",
+            Dialect::Basic,
+        ),
+        ("overflow", "inc a\ndec b\njnz 1 -2", Dialect::Basic),
+        ("echo", "in a\nout a\ninc a\nout a\njnz 1 -4", Dialect::Io),
+    ];
+
+    #[test]
+    fn test_fixture_is_current() {
+        let code = FIXTURES
+            .iter()
+            .map(|&(name, source, dialect)| {
+                transpile(&assemble(source, dialect).unwrap(), name).unwrap()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(code, include_str!("transpiler/fixture.rs"));
+    }
+
+    /// The registers a machine stops with, and its output.
+    fn run_machine(
+        (_, source, dialect): (&str, &str, Dialect),
+        registers: [i64; 4],
+        input: &[i64],
+    ) -> ([i64; 4], Vec<i64>) {
+        let instructions = assemble(source, dialect).unwrap();
+        let mut machine = Machine::new(&instructions).with_input(input.iter().copied());
+        for (reg, value) in [Reg::A, Reg::B, Reg::C, Reg::D].into_iter().zip(registers) {
+            machine[reg] = value;
+        }
+        machine.run();
+        let registers = [Reg::A, Reg::B, Reg::C, Reg::D].map(|reg| machine[reg]);
+        (registers, machine.output().to_vec())
+    }
+
+    #[test]
+    fn test_fixture_matches_machine() {
+        for c in 0..2 {
+            let registers = [0, 0, c, 0];
+            assert_eq!(
+                (fixture::fibonacci(registers), Vec::new()),
+                run_machine(FIXTURES[0], registers, &[])
+            );
+        }
+
+        // Jumping straight to instruction 0 or 5 would loop without counting down d
+        for a in [i64::MIN, -3, 1, 2, 3, 4, 6, i64::MAX] {
+            for b in [-5, 3] {
+                for d in [1, 3] {
+                    let registers = [a, b, 0, d];
+                    assert_eq!(
+                        (fixture::dynamic_jumps(registers), Vec::new()),
+                        run_machine(FIXTURES[1], registers, &[])
+                    );
+                }
+            }
+        }
+
+        // Runs until a register overflows
+        for registers in [[i64::MAX - 3, 0, 0, 0], [0, i64::MIN + 3, 0, 0]] {
+            assert_eq!(
+                (fixture::overflow(registers), Vec::new()),
+                run_machine(FIXTURES[2], registers, &[])
+            );
+        }
+
+        let input = [3, -1, i64::MAX];
+        let mut values = input.iter().copied();
+        let mut output = Vec::new();
+        let registers = fixture::echo(
+            [0; 4],
+            || values.next(),
+            |value| {
+                output.push(value);
+                true
+            },
+        );
+        assert_eq!(
+            (registers, output),
+            run_machine(FIXTURES[3], [0; 4], &input)
+        );
+    }
+
+    #[test]
+    fn test_transpile() {
+        let source = "\
+                cpy 3 a\n\
+            loop:\n\
+                inc b\n\
+                dec a\n\
+                jnz a loop\n\
+                jnz 0 0\n\
+                jnz b end\n\
+                out b\n\
+            end:\
+        ";
+        let instructions = assemble(source, Dialect::Clock).unwrap();
+        assert_eq!(
+            transpile(&instructions, "run").unwrap(),
+            "\
+#[allow(unused_mut)]
+pub fn run(registers: [i64; 4], mut out: impl FnMut(i64) -> bool) -> [i64; 4] {
+    let [mut a, mut b, mut c, mut d] = registers;
+    let mut ip = 0;
+    loop {
+        match ip {
+            0 => {
+                a = 3;
+                ip = 1;
+            }
+            1 => {
+                loop {
+                    let Some(value) = b.checked_add(1) else {
+                        return [a, b, c, d];
+                    };
+                    b = value;
+                    let Some(value) = a.checked_sub(1) else {
+                        return [a, b, c, d];
+                    };
+                    a = value;
+                    if a == 0 {
+                        break;
+                    }
+                }
+                ip = 4;
+            }
+            4 => {
+                // jnz 0 0
+                if b != 0 {
+                    break;
+                } else {
+                    ip = 6;
+                }
+            }
+            6 => {
+                if !out(b) {
+                    return [a, b, c, d];
+                }
+                ip = 7;
+            }
+            _ => break,
+        }
+    }
+    [a, b, c, d]
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_dynamic_jumps() {
+        let instructions = assemble("cpy 2 a\njnz 1 a\ninc b", Dialect::Basic).unwrap();
+        let code = transpile(&instructions, "run").unwrap();
+        assert!(
+            code.contains("            2 => {\n                let Some(value) = b.checked_add(1)")
+        );
+        assert!(code.contains(
+            "ip = 1_i64.checked_add(a).and_then(|ip| usize::try_from(ip).ok()).unwrap_or(usize::MAX);"
+        ));
+    }
+
+    #[test]
+    fn test_input() {
+        let instructions = assemble("in b", Dialect::Io).unwrap();
+        let code = transpile(&instructions, "run").unwrap();
+        assert!(
+            code.contains(
+                "pub fn run(registers: [i64; 4], mut input: impl FnMut() -> Option<i64>)"
+            )
+        );
+        assert!(code.contains(
+            "
+                let Some(value) = input() else {
+                    return [a, b, c, d];
+                };
+                b = value;
+"
+        ));
+    }
+
+    #[test]
+    fn test_toggles() {
+        let instructions = assemble("tgl 3\ninc a\ntgl -3", Dialect::Toggle).unwrap();
+        assert!(transpile(&instructions, "run").is_ok());
+        let instructions = assemble("tgl 1\ninc a", Dialect::Toggle).unwrap();
+        assert!(matches!(
+            transpile(&instructions, "run"),
+            Err(TranspileError::Toggle(0))
+        ));
+        let instructions = assemble("inc a\ntgl a", Dialect::Toggle).unwrap();
+        assert!(matches!(
+            transpile(&instructions, "run"),
+            Err(TranspileError::Toggle(1))
+        ));
+    }
+
+    #[test]
+    fn test_named_registers() {
+        let instructions = assemble("cpy 2 total\ninc total\ncpy total a", Dialect::Basic).unwrap();
+        let code = transpile(&instructions, "run").unwrap();
+        assert!(code.contains(", mut total] = registers;"));
+        assert!(code.contains("                total = value;\n                a = total;\n"));
+        let instructions = assemble("inc loop", Dialect::Basic).unwrap();
+        assert!(matches!(
+            transpile(&instructions, "run"),
+            Err(TranspileError::RegisterName(name)) if name == "loop"
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+#[allow(unused_mut)]
+pub fn fibonacci(registers: [i64; 4]) -> [i64; 4] {
+    let [mut a, mut b, mut c, mut d] = registers;
+    let mut ip = 0;
+    loop {
+        match ip {
+            0 => {
+                a = 1;
+                b = 1;
+                d = 26;
+                if c != 0 {
+                    ip = 5;
+                } else {
+                    ip = 4;
+                }
+            }
+            4 => {
+                ip = 9;
+            }
+            5 => {
+                c = 7;
+                ip = 6;
+            }
+            6 => {
+                loop {
+                    let Some(value) = d.checked_add(1) else {
+                        return [a, b, c, d];
+                    };
+                    d = value;
+                    let Some(value) = c.checked_sub(1) else {
+                        return [a, b, c, d];
+                    };
+                    c = value;
+                    if c == 0 {
+                        break;
+                    }
+                }
+                ip = 9;
+            }
+            9 => {
+                c = a;
+                ip = 10;
+            }
+            10 => {
+                loop {
+                    let Some(value) = a.checked_add(1) else {
+                        return [a, b, c, d];
+                    };
+                    a = value;
+                    let Some(value) = b.checked_sub(1) else {
+                        return [a, b, c, d];
+                    };
+                    b = value;
+                    if b == 0 {
+                        break;
+                    }
+                }
+                ip = 13;
+            }
+            13 => {
+                b = c;
+                let Some(value) = d.checked_sub(1) else {
+                    return [a, b, c, d];
+                };
+                d = value;
+                if d != 0 {
+                    ip = 9;
+                } else {
+                    ip = 16;
+                }
+            }
+            _ => break,
+        }
+    }
+    [a, b, c, d]
+}
+
+#[allow(unused_mut)]
+pub fn dynamic_jumps(registers: [i64; 4]) -> [i64; 4] {
+    let [mut a, mut b, mut c, mut d] = registers;
+    let mut ip = 0;
+    loop {
+        match ip {
+            0 => {
+                ip = 0_i64.checked_add(a).and_then(|ip| usize::try_from(ip).ok()).unwrap_or(usize::MAX);
+            }
+            1 => {
+                let Some(value) = b.checked_add(1) else {
+                    return [a, b, c, d];
+                };
+                b = value;
+                ip = 2;
+            }
+            2 => {
+                let Some(value) = b.checked_add(1) else {
+                    return [a, b, c, d];
+                };
+                b = value;
+                ip = 3;
+            }
+            3 => {
+                let Some(value) = c.checked_add(1) else {
+                    return [a, b, c, d];
+                };
+                c = value;
+                ip = 4;
+            }
+            4 => {
+                let Some(value) = d.checked_sub(1) else {
+                    return [a, b, c, d];
+                };
+                d = value;
+                ip = 5;
+            }
+            5 => {
+                if d != 0 {
+                    ip = 5_i64.checked_add(b).and_then(|ip| usize::try_from(ip).ok()).unwrap_or(usize::MAX);
+                } else {
+                    ip = 6;
+                }
+            }
+            _ => break,
+        }
+    }
+    [a, b, c, d]
+}
+
+#[allow(unused_mut)]
+pub fn overflow(registers: [i64; 4]) -> [i64; 4] {
+    let [mut a, mut b, mut c, mut d] = registers;
+    let mut ip = 0;
+    loop {
+        match ip {
+            0 => {
+                let Some(value) = a.checked_add(1) else {
+                    return [a, b, c, d];
+                };
+                a = value;
+                let Some(value) = b.checked_sub(1) else {
+                    return [a, b, c, d];
+                };
+                b = value;
+                ip = 0;
+            }
+            _ => break,
+        }
+    }
+    [a, b, c, d]
+}
+
+#[allow(unused_mut)]
+pub fn echo(registers: [i64; 4], mut input: impl FnMut() -> Option<i64>, mut out: impl FnMut(i64) -> bool) -> [i64; 4] {
+    let [mut a, mut b, mut c, mut d] = registers;
+    let mut ip = 0;
+    loop {
+        match ip {
+            0 => {
+                let Some(value) = input() else {
+                    return [a, b, c, d];
+                };
+                a = value;
+                if !out(a) {
+                    return [a, b, c, d];
+                }
+                let Some(value) = a.checked_add(1) else {
+                    return [a, b, c, d];
+                };
+                a = value;
+                if !out(a) {
+                    return [a, b, c, d];
+                }
+                ip = 0;
+            }
+            _ => break,
+        }
+    }
+    [a, b, c, d]
+}
@@ -0,0 +1,223 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+
+use std::io::Write;
+
+use advent_of_code_2016::assembunny::cfg::ControlFlowGraph;
+use advent_of_code_2016::assembunny::{
+    Debugger, Dialect, Instruction, Machine, Reg, StopReason, WatchCondition, Watchpoint,
+    decompile, parse,
+};
+
+const HELP: &str = "\
+Commands:
+  s [n]          step n instructions (default 1)
+  c              continue until a breakpoint, watchpoint or halt
+  r [n]          step back n instructions (default 1)
+  rc             continue backwards
+  b <ip>         toggle a breakpoint
+  w <reg> [val]  watch a register for changes, or for becoming val
+  uw <reg>       remove watchpoints on a register
+  set <reg> <v>  set a register
+  in <v>...      queue values to be read by `in`
+  p              print registers and program
+  l              print decompiled pseudocode
+  g              print the control flow graph in DOT format
+  q              quit";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("Usage: assembunny_debugger <program> [basic|toggle|clock|io] [reg=value...]");
+        std::process::exit(2);
+    };
+    let mut dialect = Dialect::Toggle;
+    let mut initial = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "basic" => dialect = Dialect::Basic,
+            "toggle" => dialect = Dialect::Toggle,
+            "clock" => dialect = Dialect::Clock,
+            "io" => dialect = Dialect::Io,
+            _ => match arg
+                .split_once('=')
+                .map(|(reg, value)| (reg.parse::<Reg>(), value.parse()))
+            {
+                Some((Ok(reg), Ok(value))) => initial.push((reg, value)),
+                _ => fail(&format!("Invalid argument {arg:?}")),
+            },
+        }
+    }
+    let source = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| fail(&format!("Could not read {path}: {err}")));
+    let instructions = parse(source.trim_end(), dialect)
+        .unwrap_or_else(|err| fail(&format!("Could not parse {path}: {err}")));
+    let mut debugger = Debugger::new(Machine::new(&instructions));
+    for (reg, value) in initial {
+        if !debugger.machine().regs().any(|known| known == reg) {
+            fail(&format!("The program does not use register {reg}"));
+        }
+        debugger.set_register(reg, value);
+    }
+    repl(&mut debugger, &instructions);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
+}
+
+fn repl(debugger: &mut Debugger, instructions: &[Instruction]) {
+    let mut line = String::new();
+    print_location(debugger);
+    loop {
+        print!("(abd) ");
+        std::io::stdout().flush().unwrap();
+        line.clear();
+        if !matches!(std::io::stdin().read_line(&mut line), Ok(1..)) {
+            break;
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] => {}
+            ["q" | "quit"] => break,
+            ["h" | "help"] => println!("{HELP}"),
+            ["s" | "step", count @ ..] => {
+                let reason = repeat(count, || debugger.step());
+                report(debugger, reason);
+            }
+            ["r" | "back", count @ ..] => {
+                let reason = repeat(count, || debugger.step_back());
+                report(debugger, reason);
+            }
+            ["c" | "continue"] => {
+                let reason = debugger.resume();
+                report(debugger, reason);
+            }
+            ["rc"] => {
+                let reason = debugger.reverse();
+                report(debugger, reason);
+            }
+            ["b" | "break", ip] => match ip.parse() {
+                Ok(ip) if ip < instructions.len() => {
+                    if debugger.remove_breakpoint(ip) {
+                        println!("Removed breakpoint at {ip}");
+                    } else {
+                        debugger.add_breakpoint(ip);
+                        println!("Added breakpoint at {ip}");
+                    }
+                }
+                _ => println!("Invalid instruction index {ip:?}"),
+            },
+            ["w" | "watch", reg, value @ ..] => {
+                let condition = match value {
+                    [] => Some(WatchCondition::Changed),
+                    [value] => value.parse().ok().map(WatchCondition::Equals),
+                    _ => None,
+                };
+                match (register(debugger, reg), condition) {
+                    (Ok(reg), Some(condition)) => {
+                        debugger.add_watchpoint(Watchpoint { reg, condition });
+                    }
+                    _ => println!("Usage: w <reg> [value]"),
+                }
+            }
+            ["uw", reg] => match register(debugger, reg) {
+                Ok(reg) => debugger.remove_watchpoints(reg),
+                Err(err) => println!("{err}"),
+            },
+            ["set", reg, value] => match (register(debugger, reg), value.parse()) {
+                (Ok(reg), Ok(value)) => debugger.set_register(reg, value),
+                _ => println!("Usage: set <reg> <value>"),
+            },
+            ["in", values @ ..] if !values.is_empty() => {
+                match values
+                    .iter()
+                    .map(|value| value.parse())
+                    .collect::<Result<Vec<_>, _>>()
+                {
+                    Ok(values) => values
+                        .into_iter()
+                        .for_each(|value| debugger.push_input(value)),
+                    Err(err) => println!("{err}"),
+                }
+            }
+            ["p" | "print"] => print!("{}", debugger.machine()),
+            ["l" | "list"] => print!("{}", decompile(debugger.machine().instructions())),
+            ["g" | "graph"] => {
+                let instructions = debugger.machine().instructions();
+                print!(
+                    "{}",
+                    ControlFlowGraph::new(instructions).to_dot(instructions)
+                );
+            }
+            _ => println!("Unknown command. Type 'help' for a list of commands."),
+        }
+    }
+}
+
+/// Runs `action` the requested number of times, stopping early on anything but a plain step.
+fn repeat(count: &[&str], mut action: impl FnMut() -> StopReason) -> StopReason {
+    let count = count
+        .first()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1);
+    let mut reason = StopReason::Stepped;
+    for _ in 0..count {
+        reason = action();
+        if reason != StopReason::Stepped {
+            break;
+        }
+    }
+    reason
+}
+
+fn report(debugger: &Debugger, reason: StopReason) {
+    match reason {
+        StopReason::Stepped => {}
+        StopReason::Breakpoint(ip) => println!("Breakpoint at {ip}"),
+        StopReason::Watchpoint(Watchpoint { reg, .. }) => {
+            println!("Watchpoint: {reg} = {}", debugger.machine()[reg]);
+        }
+        StopReason::Halted => match debugger.machine().overflow() {
+            Some(overflow) => println!("Halted: {overflow}"),
+            None => println!("Halted"),
+        },
+        StopReason::Waiting => println!("Waiting for input"),
+        StopReason::Start => println!("Reached the start of the history"),
+    }
+    print_location(debugger);
+}
+
+/// Parses the name of a register in the register file of the machine.
+fn register(debugger: &Debugger, name: &str) -> Result<Reg, String> {
+    let reg = name.parse::<Reg>().map_err(|err| err.to_string())?;
+    if debugger.machine().regs().any(|known| known == reg) {
+        Ok(reg)
+    } else {
+        Err(format!("The program does not use register {reg}"))
+    }
+}
+
+fn print_location(debugger: &Debugger) {
+    let machine = debugger.machine();
+    let registers = machine
+        .regs()
+        .map(|reg| format!("{reg}={}", machine[reg]))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let output = if machine.output().is_empty() {
+        String::new()
+    } else {
+        format!(" out={:?}", machine.output())
+    };
+    match machine.instructions().get(machine.ip()) {
+        Some(instruction) if !machine.is_stopped() => {
+            println!(
+                "[{}] {registers}{output}  {:2}: {instruction}",
+                debugger.steps(),
+                machine.ip()
+            );
+        }
+        _ => println!("[{}] {registers}{output}  (stopped)", debugger.steps()),
+    }
+}
@@ -1,10 +1,11 @@
-use std::collections::HashSet;
 use std::num::ParseIntError;
 use std::ops::{Add, AddAssign, Mul, MulAssign};
 use std::str::FromStr;
 
 use thiserror::Error;
 
+use crate::utils::SparseGrid;
+
 #[derive(Debug, Error)]
 enum ParseError {
     #[error("Syntax error")]
@@ -157,14 +158,15 @@ fn part_1(input: &[Step]) -> u32 {
 
 #[aoc(day1, part2)]
 fn part_2(input: &[Step]) -> u32 {
-    let mut seen = HashSet::new();
+    let mut seen = SparseGrid::filled(false);
     let mut state = Position::default();
-    seen.insert((0, 0));
+    seen.insert((0, 0), true);
     for &step in input {
         state.dir *= step.turn;
         for _ in 1..=step.dist {
             state += state.dir;
-            if !seen.insert((state.x, state.y)) {
+            let pos = (i64::from(state.y), i64::from(state.x));
+            if std::mem::replace(seen.get_mut(pos), true) {
                 return state.dist();
             }
         }
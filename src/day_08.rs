@@ -3,6 +3,8 @@ use std::str::FromStr;
 
 use thiserror::Error;
 
+use crate::utils::{Glyph, Grid};
+
 #[derive(Debug, Error)]
 enum ParseError {
     #[error("Syntax error")]
@@ -58,20 +60,12 @@ fn part_1(instructions: &[Instruction]) -> usize {
 
 #[aoc(day8, part2)]
 fn part_2(instructions: &[Instruction]) -> String {
-    let grid: [[bool; 50]; 6] = execute(instructions);
-    let mut display = String::new();
-    for (row1, row2) in grid.iter().step_by(2).zip(grid.iter().skip(1).step_by(2)) {
-        display.push('\n');
-        for (&val1, &val2) in row1.iter().zip(row2) {
-            display.push(match (val1, val2) {
-                (true, true) => '█',
-                (true, false) => '▀',
-                (false, true) => '▄',
-                (false, false) => ' ',
-            });
-        }
-    }
-    display
+    let screen: [[bool; 50]; 6] = execute(instructions);
+    let text = Grid::from(screen)
+        .render(bool::glyph)
+        .half_blocks(|glyph| glyph == '#');
+    // One line before each pair of rows, so the first one starts below the answer label
+    text.lines().flat_map(|line| ["\n", line]).collect()
 }
 
 fn execute<const R: usize, const C: usize>(instructions: &[Instruction]) -> [[bool; C]; R] {
@@ -1,11 +1,15 @@
-use std::collections::{HashSet, VecDeque};
 use std::num::ParseIntError;
 
+use crate::utils::SparseGrid;
+
 struct Maze {
     seed: u64,
 }
 
 impl Maze {
+    /// Longer than any path through the mazes of the puzzle.
+    const MAX_PATH: usize = 1000;
+
     const fn new(seed: u64) -> Self {
         Self { seed }
     }
@@ -14,49 +18,25 @@ impl Maze {
         ((3 + x + 2 * y) * x + (1 + y) * y + self.seed).count_ones() & 1 == 0
     }
 
-    fn neighbors(&self, (x, y): (u64, u64), dist: u64, queue: &mut VecDeque<(u64, u64, u64)>) {
-        for (x1, y1) in [
-            x.checked_sub(1).map(|x1| (x1, y)),
-            y.checked_sub(1).map(|y1| (x, y1)),
-            Some((x + 1, y)),
-            Some((x, y + 1)),
-        ]
-        .iter()
-        .filter_map(|pt| pt.filter(|&(x, y)| self.is_open(x, y)))
-        {
-            queue.push_back((x1, y1, dist));
-        }
+    /// The maze by `(y, x)`, with walls at negative coordinates.
+    fn grid(&self) -> SparseGrid<bool> {
+        let maze = Self::new(self.seed);
+        SparseGrid::new(move |(y, x)| {
+            u64::try_from(x)
+                .ok()
+                .zip(u64::try_from(y).ok())
+                .is_some_and(|(x, y)| maze.is_open(x, y))
+        })
     }
 
-    fn find_path(&self, source: (u64, u64), dest: (u64, u64)) -> u64 {
-        let mut visited = HashSet::new();
-        let mut pending = VecDeque::new();
-        pending.push_back((source.0, source.1, 0));
-        while let Some((x, y, dist)) = pending.pop_front() {
-            if !visited.insert((x, y)) {
-                continue;
-            }
-            if (x, y) == dest {
-                return dist;
-            }
-            self.neighbors((x, y), dist + 1, &mut pending);
-        }
-        0
+    fn find_path(&self, (x0, y0): (i64, i64), (x1, y1): (i64, i64)) -> usize {
+        self.grid()
+            .shortest_path((y0, x0), (y1, x1), Self::MAX_PATH)
+            .unwrap_or_default()
     }
 
-    fn find_in_range(&self, source: (u64, u64), max_dist: u64) -> usize {
-        let mut visited = HashSet::new();
-        let mut pending = VecDeque::new();
-        pending.push_back((source.0, source.1, 0));
-        while let Some((x, y, dist)) = pending.pop_front() {
-            if !visited.insert((x, y)) {
-                continue;
-            }
-            if dist < max_dist {
-                self.neighbors((x, y), dist + 1, &mut pending);
-            }
-        }
-        visited.len()
+    fn find_in_range(&self, (x, y): (i64, i64), max_dist: usize) -> usize {
+        self.grid().reachable((y, x), max_dist).len()
     }
 }
 
@@ -66,7 +46,7 @@ fn parse(input: &str) -> Result<Maze, ParseIntError> {
 }
 
 #[aoc(day13, part1)]
-fn part_1(maze: &Maze) -> u64 {
+fn part_1(maze: &Maze) -> usize {
     maze.find_path((1, 1), (31, 39))
 }
 
@@ -78,18 +58,15 @@ fn part_2(maze: &Maze) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::Grid;
 
     #[test]
     fn test_walls() {
         let maze = Maze::new(10);
 
-        let mut display = String::with_capacity(77);
-        for y in 0..=6 {
-            for x in 0..=9 {
-                display.push(if maze.is_open(x, y) { '.' } else { '#' });
-            }
-            display.push('\n');
-        }
+        let display = Grid::from_fn(7, 10, |(y, x)| maze.is_open(x as u64, y as u64))
+            .render(|&open| if open { '.' } else { '#' })
+            .to_string();
 
         assert_eq!(
             display,
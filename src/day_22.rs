@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use thiserror::Error;
 
-use crate::utils::{Grid, TilePath};
+use crate::utils::{Grid, Route, TilePath};
 
 #[derive(Debug, Error)]
 enum ParseError {
@@ -77,13 +77,20 @@ fn part_1(nodes: &[NetworkNode]) -> u32 {
 #[aoc(day22, part2)]
 fn part_2(nodes: &[NetworkNode]) -> usize {
     let grid: Grid<Tile> = nodes.try_into().unwrap();
+    let (empty_route, target_route) = routes(&grid);
+    empty_route.distance + 5 * (target_route.distance - 1) + 1
+}
+
+/// The route of the empty node to the front of the target data, and the route the target data
+/// then takes to the goal.
+fn routes(grid: &Grid<Tile>) -> (Route, Route) {
     let empty_pos = grid.find_pos(|&tile| tile == Tile::Empty).unwrap();
     let target_pos = (0, grid.cols() - 1);
     let front_of_target = (target_pos.0, target_pos.1 - 1);
     let goal_pos = (0, 0);
-    let move_empty_to_front_of_target = grid.shortest_path(empty_pos, front_of_target).unwrap();
-    let move_target_to_goal = grid.shortest_path(target_pos, goal_pos).unwrap();
-    move_empty_to_front_of_target + 5 * (move_target_to_goal - 1) + 1
+    let move_empty_to_front_of_target = grid.shortest_route(empty_pos, front_of_target).unwrap();
+    let move_target_to_goal = grid.shortest_route(target_pos, goal_pos).unwrap();
+    (move_empty_to_front_of_target, move_target_to_goal)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -183,6 +190,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_routes() {
+        let nodes = parse(EXAMPLE).unwrap();
+        let grid: Grid<Tile> = nodes.as_slice().try_into().unwrap();
+        let (empty_route, target_route) = routes(&grid);
+        assert_eq!(empty_route.cells, [(1, 1), (0, 1)]);
+        assert_eq!(target_route.cells, [(0, 2), (0, 1), (0, 0)]);
+    }
+
     #[test]
     fn test_part_2() {
         let nodes = parse(EXAMPLE).unwrap();
@@ -1,264 +1,31 @@
-use std::fmt::Display;
-use std::num::ParseIntError;
-use std::ops::{Index, IndexMut};
-use std::str::FromStr;
-
-use thiserror::Error;
-
-#[derive(Debug, Error)]
-enum ParseError {
-    #[error("Invalid instruction name or syntax")]
-    SyntaxError,
-    #[error("Invalid register name")]
-    InvalidRegister,
-    #[error(transparent)]
-    InvalidNumber(#[from] ParseIntError),
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Instruction {
-    /// Copy
-    Cpy(RegOrValue, RegOrValue),
-    /// Increase
-    Inc(RegOrValue),
-    /// Decrease
-    Dec(RegOrValue),
-    /// Jump if not zero
-    Jnz(RegOrValue, RegOrValue),
-    /// Toggle
-    Tgl(RegOrValue),
-}
-
-impl FromStr for Instruction {
-    type Err = ParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.split_once(' ').ok_or(ParseError::SyntaxError)? {
-            ("cpy", rest) => {
-                let (a, b) = rest.split_once(' ').ok_or(ParseError::SyntaxError)?;
-                Self::Cpy(a.parse()?, b.parse()?)
-            }
-            ("inc", rest) => Self::Inc(rest.parse()?),
-            ("dec", rest) => Self::Dec(rest.parse()?),
-            ("jnz", rest) => {
-                let (a, b) = rest.split_once(' ').ok_or(ParseError::SyntaxError)?;
-                Self::Jnz(a.parse()?, b.parse()?)
-            }
-            ("tgl", rest) => Self::Tgl(rest.parse()?),
-            _ => return Err(ParseError::SyntaxError),
-        })
-    }
-}
-
-impl Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Self::Cpy(a, b) => write!(f, "cpy {a} {b}"),
-            Self::Inc(a) => write!(f, "inc {a}"),
-            Self::Dec(a) => write!(f, "dec {a}"),
-            Self::Jnz(a, b) => write!(f, "jnz {a} {b}"),
-            Self::Tgl(a) => write!(f, "tgl {a}"),
-        }
-    }
-}
-
-impl Instruction {
-    const fn toggle(self) -> Self {
-        match self {
-            Self::Cpy(a, b) => Self::Jnz(a, b),
-            Self::Inc(a) => Self::Dec(a),
-            Self::Dec(a) | Self::Tgl(a) => Self::Inc(a),
-            Self::Jnz(a, b) => Self::Cpy(a, b),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum RegOrValue {
-    Reg(Reg),
-    Value(isize),
-}
-
-impl FromStr for RegOrValue {
-    type Err = ParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.as_bytes() {
-            [b'0'..=b'9' | b'-', ..] => Self::Value(s.parse()?),
-            _ => Self::Reg(s.parse()?),
-        })
-    }
-}
-
-impl Display for RegOrValue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Reg(reg) => write!(f, "{reg}"),
-            Self::Value(val) => write!(f, "{val}"),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Reg {
-    A,
-    B,
-    C,
-    D,
-}
-
-impl FromStr for Reg {
-    type Err = ParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "a" => Self::A,
-            "b" => Self::B,
-            "c" => Self::C,
-            "d" => Self::D,
-            _ => return Err(ParseError::InvalidRegister),
-        })
-    }
-}
-
-impl Display for Reg {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{self:?}")
-    }
-}
+use crate::assembunny::{Dialect, Instruction, Machine, ParseError, Reg, parse};
 
 #[aoc_generator(day23)]
-fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
-    input.lines().map(str::parse).collect()
+fn parse_input(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    parse(input, Dialect::Toggle)
 }
 
 #[aoc(day23, part1)]
-fn part_1(instructions: &[Instruction]) -> isize {
-    let mut machine = Machine::new(instructions);
-    // machine[Reg::A] = 7;
-    // machine.run();
-    // machine[Reg::A]
-    machine[Reg::A] = 6;
-    machine.run();
-    machine[Reg::A] - (1..=6).product::<isize>() + (1..=7).product::<isize>()
+fn part_1(instructions: &[Instruction]) -> i64 {
+    run_with_eggs(instructions, 7)
 }
 
 #[aoc(day23, part2)]
-fn part_2(instructions: &[Instruction]) -> isize {
-    let mut machine = Machine::new(instructions);
-    // machine[Reg::A] = 12;
-    // machine.run();
-    // machine[Reg::A]
-    machine[Reg::A] = 6;
-    machine.run();
-    machine[Reg::A] - (1..=6).product::<isize>() + (1..=12).product::<isize>()
-}
-
-#[derive(Debug, Clone)]
-struct Machine {
-    instructions: Vec<Instruction>,
-    ip: usize,
-    registers: [isize; 4],
-    stopped: bool,
-}
-
-impl Machine {
-    fn new(instructions: &[Instruction]) -> Self {
-        Self {
-            instructions: instructions.to_vec(),
-            ip: 0,
-            registers: [0; 4],
-            stopped: false,
-        }
-    }
-
-    fn get_value(&self, source: RegOrValue) -> isize {
-        match source {
-            RegOrValue::Reg(reg) => self[reg],
-            RegOrValue::Value(v) => v,
-        }
-    }
-
-    fn step(&mut self) {
-        if self.stopped {
-            return;
-        }
-        match self.instructions[self.ip] {
-            Instruction::Cpy(value, reg) => {
-                if let RegOrValue::Reg(reg) = reg {
-                    self[reg] = self.get_value(value);
-                }
-            }
-            Instruction::Inc(reg) => {
-                if let RegOrValue::Reg(reg) = reg {
-                    self[reg] += 1;
-                }
-            }
-            Instruction::Dec(reg) => {
-                if let RegOrValue::Reg(reg) = reg {
-                    self[reg] -= 1;
-                }
-            }
-            Instruction::Jnz(condition, distance) => {
-                if self.get_value(condition) != 0 {
-                    if let Some(new_ip) = self.ip.checked_add_signed(self.get_value(distance))
-                        && new_ip < self.instructions.len()
-                    {
-                        self.ip = new_ip;
-                    } else {
-                        self.stopped = true;
-                    }
-                    return;
-                }
-            }
-            Instruction::Tgl(distance) => {
-                if let Some(new_ip) = self.ip.checked_add_signed(self.get_value(distance))
-                    && new_ip < self.instructions.len()
-                {
-                    self.instructions[new_ip] = self.instructions[new_ip].toggle();
-                }
-            }
-        }
-        self.ip += 1;
-        self.stopped = self.ip >= self.instructions.len();
-    }
-
-    fn run(&mut self) {
-        while !self.stopped {
-            self.step();
-        }
-    }
-}
-
-impl Index<Reg> for Machine {
-    type Output = isize;
-
-    fn index(&self, index: Reg) -> &Self::Output {
-        &self.registers[index as usize]
-    }
-}
-
-impl IndexMut<Reg> for Machine {
-    fn index_mut(&mut self, index: Reg) -> &mut Self::Output {
-        &mut self.registers[index as usize]
-    }
+fn part_2(instructions: &[Instruction]) -> i64 {
+    run_with_eggs(instructions, 12)
 }
 
-impl Display for Machine {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let [reg_a, reg_b, reg_c, reg_d] = self.registers;
-        writeln!(f, "A: {reg_a}, B: {reg_b}, C: {reg_c}, D: {reg_d}")?;
-        for (i, instr) in self.instructions.iter().enumerate() {
-            let active = if self.ip == i { '>' } else { ' ' };
-            writeln!(f, "{i:2}) {active} {instr}")?;
-        }
-        writeln!(f, "---")
-    }
+fn run_with_eggs(instructions: &[Instruction], eggs: i64) -> i64 {
+    let mut machine = Machine::new(instructions).with_optimizer();
+    machine[Reg::A] = eggs;
+    machine.run();
+    machine[Reg::A]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::assembunny::RegOrValue;
 
     const EXAMPLE: &str = "\
         cpy 2 a\n\
@@ -272,7 +39,7 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let result = parse(EXAMPLE).unwrap();
+        let result = parse_input(EXAMPLE).unwrap();
         assert_eq!(
             result,
             [
@@ -289,8 +56,67 @@ mod tests {
 
     #[test]
     fn test_part_1() {
-        let instructions = parse(EXAMPLE).unwrap();
+        let instructions = parse_input(EXAMPLE).unwrap();
         let result = part_1(&instructions);
         assert_eq!(result, 3);
     }
+
+    /// Computes `a! + 77 * 73`, toggling its own tail as `b` counts down.
+    const FACTORIAL: &str = "\
+        cpy a b\n\
+        dec b\n\
+        cpy a d\n\
+        cpy 0 a\n\
+        cpy b c\n\
+        inc a\n\
+        dec c\n\
+        jnz c -2\n\
+        dec d\n\
+        jnz d -5\n\
+        dec b\n\
+        cpy b c\n\
+        cpy c d\n\
+        dec d\n\
+        inc c\n\
+        jnz d -2\n\
+        tgl c\n\
+        cpy -16 c\n\
+        jnz 1 c\n\
+        cpy 77 c\n\
+        jnz 73 d\n\
+        inc a\n\
+        inc d\n\
+        jnz d -2\n\
+        inc c\n\
+        jnz c -5\
+    ";
+
+    #[test]
+    fn test_optimized_matches_interpreter() {
+        let instructions = parse_input(FACTORIAL).unwrap();
+        let mut machine = Machine::new(&instructions);
+        machine[Reg::A] = 7;
+        machine.run();
+        assert_eq!(machine[Reg::A], 5040 + 77 * 73);
+        assert_eq!(part_1(&instructions), 5040 + 77 * 73);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let instructions = parse_input(FACTORIAL).unwrap();
+        let mut machine = Machine::new(&instructions).with_optimizer();
+        machine[Reg::A] = 21;
+        machine.run();
+        assert_eq!(
+            machine.overflow().map(|overflow| overflow.reg),
+            Some(Reg::A)
+        );
+    }
+
+    #[test]
+    fn test_part_2() {
+        let instructions = parse_input(FACTORIAL).unwrap();
+        let result = part_2(&instructions);
+        assert_eq!(result, 479_001_600 + 77 * 73);
+    }
 }
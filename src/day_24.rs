@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::utils::{Grid, GridParseError, TilePath, permute};
+use crate::utils::{Grid, GridParseError, Route, TilePath, permute};
 
 #[derive(Debug, Error)]
 enum TileParseError {
@@ -51,53 +51,77 @@ fn part_2(grid: &Grid<Tile>) -> usize {
 }
 
 fn find_shortest_distance(grid: &Grid<Tile>, close_path: bool) -> usize {
+    find_shortest_route(grid, close_path).map_or(usize::MAX, |route| route.distance)
+}
+
+/// The shortest route from target 0 that visits every other target, and returns to 0 if
+/// `close_path` is set.
+fn find_shortest_route(grid: &Grid<Tile>, close_path: bool) -> Option<Route> {
     let locations = (b'0'..=b'9')
         .map(|ch| Tile::Target(ch - b'0'))
         .filter_map(|t| grid.find_pos(|&t1| t1 == t))
         .collect::<Vec<_>>();
 
-    let distances: Vec<Vec<_>> = locations
+    let routes: Vec<Vec<_>> = locations
         .iter()
         .map(|&source| {
-            let mut dists = vec![None; locations.len()];
+            let mut routes = vec![None; locations.len()];
             let is_target = |tile: &Tile| matches!(tile, Tile::Target(..));
-            for (dist, &target) in grid.all_shortest_paths(source, is_target) {
+            for (route, &target) in grid.all_shortest_routes(source, is_target) {
                 if let Tile::Target(target) = target
-                    && dist > 0
+                    && route.distance > 0
                 {
-                    dists[target as usize] = Some(dist);
+                    routes[target as usize] = Some(route);
                 }
             }
-            dists
+            routes
         })
         .collect();
+    let distance = |from: usize, to: usize| routes[from][to].as_ref().map(|route| route.distance);
 
     let mut remaining = (1..locations.len()).collect::<Vec<_>>();
-    let mut min_distance = usize::MAX;
+    let mut best: Option<(usize, Vec<usize>)> = None;
     permute(&mut remaining, &mut |sequence: &[usize]| {
         let mut dist = 0;
         let mut prev = 0;
         for &next in sequence {
-            let Some(step) = distances[prev][next] else {
+            let Some(step) = distance(prev, next) else {
                 return;
             };
             dist += step;
             prev = next;
         }
         if close_path {
-            let Some(close_dist) = distances[prev][0] else {
+            let Some(close_dist) = distance(prev, 0) else {
                 return;
             };
             dist += close_dist;
         }
-        min_distance = min_distance.min(dist);
+        if best
+            .as_ref()
+            .is_none_or(|(min_distance, _)| dist < *min_distance)
+        {
+            best = Some((dist, sequence.to_vec()));
+        }
     });
-    min_distance
+
+    let (distance, mut sequence) = best?;
+    sequence.insert(0, 0);
+    if close_path {
+        sequence.push(0);
+    }
+    let mut cells = vec![*locations.first()?];
+    for pair in sequence.windows(2) {
+        let route = routes[pair[0]][pair[1]].as_ref()?;
+        cells.extend_from_slice(&route.cells[1..]);
+    }
+    Some(Route { distance, cells })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::manhattan;
 
     const EXAMPLE: &str = "\
         ###########\n\
@@ -138,6 +162,32 @@ mod tests {
         assert_eq!(result, 14);
     }
 
+    #[test]
+    fn test_route() {
+        let grid = parse(EXAMPLE).unwrap();
+        let route = find_shortest_route(&grid, false).unwrap();
+        assert_eq!(route.distance, 14);
+        assert_eq!(route.cells.len(), 15);
+        assert_eq!(route.cells.first(), Some(&(1, 1)));
+        assert_eq!(route.cells.last(), Some(&(3, 9)));
+        for pair in route.cells.windows(2) {
+            assert_eq!(manhattan(pair[0], pair[1]), 1);
+            assert!(grid[pair[1]].is_passable());
+        }
+
+        let route = find_shortest_route(&grid, true).unwrap();
+        assert_eq!(route.distance, 20);
+        assert_eq!(route.cells.first(), route.cells.last());
+        for target in 0..=4 {
+            assert!(
+                route
+                    .cells
+                    .iter()
+                    .any(|&pos| grid[pos] == Tile::Target(target))
+            );
+        }
+    }
+
     #[test]
     fn test_part_2() {
         let grid = parse(EXAMPLE).unwrap();
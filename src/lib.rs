@@ -31,6 +31,8 @@ mod day_23;
 mod day_24;
 mod day_25;
 
+pub mod assembunny;
+
 mod utils;
 
 aoc_lib! { year = 2016 }
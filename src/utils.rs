@@ -3,18 +3,76 @@
     reason = "Library functions might or might not be used depending on each puzzle."
 )]
 
-use std::collections::VecDeque;
-use std::mem::MaybeUninit;
-use std::ops::{Index, IndexMut};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt::{Debug, Formatter};
+use std::ops::{Index, IndexMut, RangeInclusive};
 use std::str::FromStr;
 
 use thiserror::Error;
 
+mod render;
+
+pub use render::{Glyph, Render};
+
 #[derive(Debug, Clone)]
 pub struct Grid<T> {
     data: Vec<T>,
     rows: usize,
     cols: usize,
+    neighborhood: Neighborhood,
+    topology: Topology<T>,
+}
+
+/// Which cells the searches can move between.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Neighborhood {
+    /// Up, down, left and right.
+    #[default]
+    Orthogonal,
+    /// Diagonally as well.
+    Moore,
+    /// Hexagonal cells, with every odd row shifted half a cell to the right.
+    Hex,
+    /// Any set of `(row, col)` offsets.
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighborhood {
+    /// The offsets of the neighbors of a cell, which for [`Neighborhood::Hex`] depend on the row.
+    fn offsets(&self, odd_row: bool) -> &[(isize, isize)] {
+        match self {
+            Self::Orthogonal => &[(-1, 0), (0, -1), (1, 0), (0, 1)],
+            Self::Moore => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+            Self::Hex if !odd_row => &[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, -1), (1, 0)],
+            Self::Hex => &[(-1, 0), (-1, 1), (0, -1), (0, 1), (1, 0), (1, 1)],
+            Self::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// What lies past the edges of a [`Grid`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topology<T> {
+    /// Nothing.
+    Bounded,
+    /// The opposite edge.
+    Torus,
+    /// More of this tile, after the last row and column. Rows and columns still start at zero.
+    ///
+    /// Searches for a target only go past the grid, the source and the target by twice the
+    /// longest move of the neighborhood, which is enough to walk around the grid with any of the
+    /// built-in neighborhoods. Searches for every target go as far past the grid and the source.
+    Unbounded(T),
 }
 
 impl<T> Grid<T> {
@@ -23,7 +81,110 @@ impl<T> Grid<T> {
         T: Default + Copy,
     {
         let data = vec![T::default(); rows * cols];
-        Self { data, rows, cols }
+        Self::from_data(data, rows, cols)
+    }
+
+    /// A grid with the tile `tile` returns for each `(row, col)`.
+    pub fn from_fn(rows: usize, cols: usize, mut tile: impl FnMut((usize, usize)) -> T) -> Self {
+        let data = (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (row, col)))
+            .map(&mut tile)
+            .collect();
+        Self::from_data(data, rows, cols)
+    }
+
+    const fn from_data(data: Vec<T>, rows: usize, cols: usize) -> Self {
+        Self {
+            data,
+            rows,
+            cols,
+            neighborhood: Neighborhood::Orthogonal,
+            topology: Topology::Bounded,
+        }
+    }
+
+    /// Select which cells the searches move between. The default is [`Neighborhood::Orthogonal`].
+    #[must_use]
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        self
+    }
+
+    /// Select what the searches find past the edges. The default is [`Topology::Bounded`].
+    #[must_use]
+    pub fn with_topology(mut self, topology: Topology<T>) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    /// The tile at `pos`, which may be past the edges of an unbounded grid.
+    pub fn get(&self, pos: (usize, usize)) -> Option<&T> {
+        if pos.0 < self.rows && pos.1 < self.cols {
+            Some(&self[pos])
+        } else if let Topology::Unbounded(tile) = &self.topology {
+            Some(tile)
+        } else {
+            None
+        }
+    }
+
+    /// The cell `offset` away from `pos`, following the topology.
+    pub fn step(&self, pos: (usize, usize), offset: (isize, isize)) -> Option<(usize, usize)> {
+        match self.topology {
+            Topology::Bounded => Some((
+                pos.0
+                    .checked_add_signed(offset.0)
+                    .filter(|&r| r < self.rows)?,
+                pos.1
+                    .checked_add_signed(offset.1)
+                    .filter(|&c| c < self.cols)?,
+            )),
+            Topology::Torus => Some((
+                wrap(pos.0, offset.0, self.rows)?,
+                wrap(pos.1, offset.1, self.cols)?,
+            )),
+            Topology::Unbounded(_) => Some((
+                pos.0.checked_add_signed(offset.0)?,
+                pos.1.checked_add_signed(offset.1)?,
+            )),
+        }
+    }
+
+    /// The rows and columns a search from `source` to `target` stays within, on an unbounded
+    /// grid. The other topologies have nowhere else to go.
+    fn search_limit(
+        &self,
+        source: (usize, usize),
+        target: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let Topology::Unbounded(_) = self.topology else {
+            return None;
+        };
+        let longest_move = [false, true]
+            .into_iter()
+            .flat_map(|odd_row| self.neighborhood.offsets(odd_row))
+            .map(|&(row, col)| row.unsigned_abs().max(col.unsigned_abs()))
+            .max()
+            .unwrap_or_default();
+        let margin = 2 * longest_move;
+        Some((
+            self.rows.max(source.0 + 1).max(target.0 + 1) + margin,
+            self.cols.max(source.1 + 1).max(target.1 + 1) + margin,
+        ))
+    }
+
+    /// The fewest moves between two cells, ignoring their tiles. Zero if unknown.
+    fn min_moves(&self, a: (usize, usize), b: (usize, usize)) -> usize {
+        let (mut rows, mut cols) = (a.0.abs_diff(b.0), a.1.abs_diff(b.1));
+        if matches!(self.topology, Topology::Torus) {
+            rows = rows.min(self.rows - rows);
+            cols = cols.min(self.cols - cols);
+        }
+        match self.neighborhood {
+            Neighborhood::Orthogonal => rows + cols,
+            Neighborhood::Moore => rows.max(cols),
+            Neighborhood::Hex | Neighborhood::Custom(_) => 0,
+        }
     }
 
     pub fn find_pos<P>(&self, predicate: P) -> Option<(usize, usize)>
@@ -49,25 +210,18 @@ impl<T> Grid<T> {
 
 impl<T: TilePath> Grid<T> {
     pub fn shortest_path(&self, source: (usize, usize), target: (usize, usize)) -> Option<usize> {
-        let mut visited = Grid::<bool>::new(self.rows, self.cols);
-        let mut pending = VecDeque::new();
-        pending.push_back(source);
-        let mut dist = 0;
-        while !pending.is_empty() {
-            for _ in 0..pending.len() {
-                let pos = pending.pop_front().unwrap();
-                if visited[pos] {
-                    continue;
-                }
-                visited[pos] = true;
-                if pos == target {
-                    return Some(dist);
-                }
-                self.enqueue_neighbors(pos, &mut pending);
-            }
-            dist += 1;
-        }
-        None
+        let limit = self.search_limit(source, target);
+        AllShortestPathsIterator::new(self, |pos, _: &T| pos == target, source, limit)
+            .next()
+            .map(|(distance, _, _)| distance)
+    }
+
+    pub fn shortest_route(&self, source: (usize, usize), target: (usize, usize)) -> Option<Route> {
+        let limit = self.search_limit(source, target);
+        let mut paths =
+            AllShortestPathsIterator::new(self, |pos, _: &T| pos == target, source, limit);
+        let (distance, pos, _) = paths.next()?;
+        Some(paths.route(distance, pos))
     }
 
     pub fn all_shortest_paths<F>(
@@ -78,27 +232,184 @@ impl<T: TilePath> Grid<T> {
     where
         F: Fn(&T) -> bool,
     {
-        AllShortestPathsIterator::new(self, is_target, source)
+        let limit = self.search_limit(source, source);
+        AllShortestPathsIterator::new(self, move |_, tile: &T| is_target(tile), source, limit)
+            .map(|(distance, _, tile)| (distance, tile))
     }
 
-    fn enqueue_neighbors(&self, pos: (usize, usize), queue: &mut VecDeque<(usize, usize)>) {
-        queue.extend(
-            [
-                pos.0.checked_sub(1).map(|r1| (r1, pos.1)),
-                pos.1.checked_sub(1).map(|c1| (pos.0, c1)),
-                (pos.0 + 1 < self.rows).then_some((pos.0 + 1, pos.1)),
-                (pos.1 + 1 < self.cols).then_some((pos.0, pos.1 + 1)),
-            ]
-            .into_iter()
-            .flatten()
-            .filter(|&pos1| self[pos1].is_passable()),
-        );
+    /// Like [`Grid::all_shortest_paths`], with the route to each target. The target is the last
+    /// cell of its route.
+    pub fn all_shortest_routes<F>(
+        &self,
+        source: (usize, usize),
+        is_target: F,
+    ) -> impl Iterator<Item = (Route, &T)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        let limit = self.search_limit(source, source);
+        let mut paths =
+            AllShortestPathsIterator::new(self, move |_, tile: &T| is_target(tile), source, limit);
+        std::iter::from_fn(move || {
+            let (distance, pos, tile) = paths.next()?;
+            Some((paths.route(distance, pos), tile))
+        })
+    }
+
+    /// Every cell at most `max_distance` moves from `source`, with its distance, nearest first.
+    pub fn reachable(
+        &self,
+        source: (usize, usize),
+        max_distance: usize,
+    ) -> Vec<((usize, usize), usize)> {
+        AllShortestPathsIterator::new(self, |_, _: &T| true, source, None)
+            .map(|(distance, pos, _)| (pos, distance))
+            .take_while(|&(_, distance)| distance <= max_distance)
+            .collect()
+    }
+}
+
+impl<T> Grid<T> {
+    fn neighbors(&self, pos: (usize, usize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighborhood
+            .offsets(!pos.0.is_multiple_of(2))
+            .iter()
+            .filter_map(move |&offset| self.step(pos, offset))
+    }
+}
+
+/// Whether `pos` is inside the rows and columns of [`Grid::search_limit`].
+fn within(pos: (usize, usize), limit: Option<(usize, usize)>) -> bool {
+    limit.is_none_or(|(rows, cols)| pos.0 < rows && pos.1 < cols)
+}
+
+/// Moves `delta` steps from `x`, wrapping around at `len`.
+fn wrap(x: usize, delta: isize, len: usize) -> Option<usize> {
+    let delta = delta.checked_rem_euclid(isize::try_from(len).ok()?)?;
+    Some((x + delta.unsigned_abs()) % len)
+}
+
+/// A path through a [`Grid`] or [`SparseGrid`], including both ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route<P = (usize, usize)> {
+    pub distance: usize,
+    pub cells: Vec<P>,
+}
+
+impl<T: TileCost> Grid<T> {
+    pub fn dijkstra(&self, source: (usize, usize), target: (usize, usize)) -> Option<Route> {
+        self.a_star_with(source, target, |_| 0)
+    }
+
+    /// A* guided by the fewest moves to `target`, which is the Manhattan distance for the
+    /// default neighborhood, times [`TileCost::MIN_COST`].
+    pub fn a_star(&self, source: (usize, usize), target: (usize, usize)) -> Option<Route> {
+        self.a_star_with(source, target, |pos| {
+            self.min_moves(pos, target).saturating_mul(T::MIN_COST)
+        })
+    }
+
+    /// The cheapest route is only guaranteed if `heuristic` never overestimates the remaining
+    /// cost to `target`.
+    pub fn a_star_with<H>(
+        &self,
+        source: (usize, usize),
+        target: (usize, usize),
+        heuristic: H,
+    ) -> Option<Route>
+    where
+        H: Fn((usize, usize)) -> usize,
+    {
+        let limit = self.search_limit(source, target);
+        let mut distances = CellMap::new(self);
+        distances.insert(source, 0);
+        let mut previous = CellMap::new(self);
+        let mut pending = BinaryHeap::new();
+        pending.push(Reverse((heuristic(source), 0, source)));
+        while let Some(Reverse((_, dist, pos))) = pending.pop() {
+            if pos == target {
+                return Some(Route {
+                    distance: dist,
+                    cells: trace_back(|pos| previous.get(pos), target),
+                });
+            }
+            if distances.get(pos).is_some_and(|best| best < dist) {
+                continue;
+            }
+            let Some(tile) = self.get(pos) else {
+                continue;
+            };
+            for next in self.neighbors(pos).filter(|&next| within(next, limit)) {
+                let Some(cost) = self.get(next).and_then(|next| tile.move_cost(next)) else {
+                    continue;
+                };
+                let next_dist = dist + cost;
+                if distances.get(next).is_none_or(|best| next_dist < best) {
+                    distances.insert(next, next_dist);
+                    previous.insert(next, pos);
+                    pending.push(Reverse((next_dist + heuristic(next), next_dist, next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Follows the links back from `target` to the cell that has none.
+fn trace_back<P: Copy>(previous: impl Fn(P) -> Option<P>, target: P) -> Vec<P> {
+    let mut cells = vec![target];
+    while let Some(pos) = previous(*cells.last().unwrap()) {
+        cells.push(pos);
+    }
+    cells.reverse();
+    cells
+}
+
+#[must_use]
+pub const fn manhattan(a: (usize, usize), b: (usize, usize)) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+}
+
+/// What a search knows about each cell. Bounded grids keep it in a grid of their own size, while
+/// unbounded ones need a hash map for the cells past the edges.
+#[derive(Debug, Clone)]
+enum CellMap<V> {
+    Dense(Grid<Option<V>>),
+    Sparse(HashMap<(usize, usize), V>),
+}
+
+impl<V: Copy> CellMap<V> {
+    fn new<T>(grid: &Grid<T>) -> Self {
+        match grid.topology {
+            Topology::Bounded | Topology::Torus => Self::Dense(Grid::new(grid.rows, grid.cols)),
+            Topology::Unbounded(_) => Self::Sparse(HashMap::new()),
+        }
+    }
+
+    fn get(&self, pos: (usize, usize)) -> Option<V> {
+        match self {
+            Self::Dense(cells) => cells[pos],
+            Self::Sparse(cells) => cells.get(&pos).copied(),
+        }
+    }
+
+    /// Sets the value for `pos`, returning the one it replaced.
+    fn insert(&mut self, pos: (usize, usize), value: V) -> Option<V> {
+        match self {
+            Self::Dense(cells) => cells[pos].replace(value),
+            Self::Sparse(cells) => cells.insert(pos, value),
+        }
     }
 }
 
 struct AllShortestPathsIterator<'a, T, F> {
     grid: &'a Grid<T>,
-    visited: Grid<bool>,
+    /// Cells are visited when they are queued, so that each is only queued once.
+    visited: CellMap<()>,
+    /// The cell each visited cell was first reached from.
+    previous: CellMap<(usize, usize)>,
+    /// See [`Grid::search_limit`].
+    limit: Option<(usize, usize)>,
     pending: VecDeque<(usize, usize)>,
     count_same_dist: usize,
     distance: usize,
@@ -107,26 +418,44 @@ struct AllShortestPathsIterator<'a, T, F> {
 
 impl<'a, T, F> AllShortestPathsIterator<'a, T, F>
 where
-    F: Fn(&T) -> bool,
+    F: Fn((usize, usize), &T) -> bool,
 {
-    fn new(grid: &'a Grid<T>, is_target: F, source: (usize, usize)) -> Self {
+    fn new(
+        grid: &'a Grid<T>,
+        is_target: F,
+        source: (usize, usize),
+        limit: Option<(usize, usize)>,
+    ) -> Self {
+        let mut visited = CellMap::new(grid);
+        visited.insert(source, ());
         Self {
             grid,
-            visited: Grid::new(grid.rows, grid.cols),
+            visited,
+            previous: CellMap::new(grid),
+            limit,
             pending: [source].into(),
             count_same_dist: 1,
             distance: 0,
             is_target,
         }
     }
+
+    /// The route to a cell that has been visited.
+    fn route(&self, distance: usize, pos: (usize, usize)) -> Route {
+        Route {
+            distance,
+            cells: trace_back(|pos| self.previous.get(pos), pos),
+        }
+    }
 }
 
 impl<'a, T, F> Iterator for AllShortestPathsIterator<'a, T, F>
 where
     T: TilePath,
-    F: Fn(&T) -> bool,
+    F: Fn((usize, usize), &T) -> bool,
 {
-    type Item = (usize, &'a T);
+    /// The distance to a target, where it is, and its tile.
+    type Item = (usize, (usize, usize), &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(pos) = self.pending.pop_front() {
@@ -135,13 +464,21 @@ where
                 self.count_same_dist = self.pending.len() + 1;
             }
             self.count_same_dist -= 1;
-            if self.visited[pos] {
-                continue;
+            for next in self.grid.neighbors(pos) {
+                if within(next, self.limit)
+                    && self.visited.get(next).is_none()
+                    && self.grid.get(next).is_some_and(TilePath::is_passable)
+                {
+                    self.visited.insert(next, ());
+                    self.previous.insert(next, pos);
+                    self.pending.push_back(next);
+                }
             }
-            self.visited[pos] = true;
-            self.grid.enqueue_neighbors(pos, &mut self.pending);
-            if (self.is_target)(&self.grid[pos]) {
-                return Some((self.distance, &self.grid[pos]));
+            let Some(tile) = self.grid.get(pos) else {
+                continue;
+            };
+            if (self.is_target)(pos, tile) {
+                return Some((self.distance, pos, tile));
             }
         }
         None
@@ -165,20 +502,22 @@ where
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let cols = s.lines().next().unwrap().len();
         let rows = s.lines().count();
-        let data = (0..rows * cols)
-            .map(|_| MaybeUninit::uninit())
-            .collect::<Vec<_>>();
-        let mut grid = Grid { data, rows, cols };
-        for (r, line) in s.lines().enumerate() {
+        let mut data = Vec::with_capacity(rows * cols);
+        for line in s.lines() {
             if line.len() != cols {
                 return Err(GridParseError::ShapeError);
             }
-            for (c, cell) in line.bytes().enumerate() {
-                let tile: T = cell.try_into()?;
-                grid[(r, c)] = MaybeUninit::new(tile);
+            for cell in line.bytes() {
+                data.push(cell.try_into()?);
             }
         }
-        Ok(unsafe { std::mem::transmute::<Grid<MaybeUninit<T>>, Self>(grid) })
+        Ok(Self::from_data(data, rows, cols))
+    }
+}
+
+impl<T, const R: usize, const C: usize> From<[[T; C]; R]> for Grid<T> {
+    fn from(rows: [[T; C]; R]) -> Self {
+        Self::from_data(rows.into_iter().flatten().collect(), R, C)
     }
 }
 
@@ -200,6 +539,179 @@ pub trait TilePath {
     fn is_passable(&self) -> bool;
 }
 
+impl TilePath for bool {
+    fn is_passable(&self) -> bool {
+        *self
+    }
+}
+
+/// Costs for the weighted searches, such as [`Grid::dijkstra`].
+pub trait TileCost {
+    /// The least any move costs. [`Grid::a_star`] expects every remaining move to cost this
+    /// much, so it has to be a lower bound. The default of zero searches like Dijkstra.
+    const MIN_COST: usize = 0;
+
+    /// The cost of entering this tile, or `None` if it can not be entered.
+    fn cost(&self) -> Option<usize>;
+
+    /// The cost of moving from this tile to a neighbor. By default, the cost of entering it.
+    fn move_cost(&self, to: &Self) -> Option<usize> {
+        to.cost()
+    }
+}
+
+/// A cell of a [`SparseGrid`], by `(row, col)`.
+pub type Point = (i64, i64);
+
+/// A grid without edges, indexed by signed `(row, col)`. Tiles are generated when they are first
+/// looked at, so the grid can be as large as the searches need.
+pub struct SparseGrid<T> {
+    tiles: HashMap<Point, T>,
+    generate: Box<dyn Fn(Point) -> T>,
+    /// The smallest and largest row and column of any tile generated or set so far.
+    bounds: Option<(Point, Point)>,
+    neighborhood: Neighborhood,
+}
+
+impl<T> SparseGrid<T> {
+    pub fn new(generate: impl Fn(Point) -> T + 'static) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            generate: Box::new(generate),
+            bounds: None,
+            neighborhood: Neighborhood::Orthogonal,
+        }
+    }
+
+    /// Every tile starts out as `tile`.
+    pub fn filled(tile: T) -> Self
+    where
+        T: Clone + 'static,
+    {
+        Self::new(move |_| tile.clone())
+    }
+
+    /// Select which cells the searches move between. The default is [`Neighborhood::Orthogonal`].
+    #[must_use]
+    pub fn with_neighborhood(mut self, neighborhood: Neighborhood) -> Self {
+        self.neighborhood = neighborhood;
+        self
+    }
+
+    pub fn get(&mut self, pos: Point) -> &T {
+        self.get_mut(pos)
+    }
+
+    pub fn get_mut(&mut self, pos: Point) -> &mut T {
+        self.extend_bounds(pos);
+        self.tiles
+            .entry(pos)
+            .or_insert_with(|| (self.generate)(pos))
+    }
+
+    pub fn insert(&mut self, pos: Point, tile: T) {
+        self.extend_bounds(pos);
+        self.tiles.insert(pos, tile);
+    }
+
+    /// The rows and columns of every tile generated or set so far.
+    #[must_use]
+    pub fn bounds(&self) -> Option<(RangeInclusive<i64>, RangeInclusive<i64>)> {
+        self.bounds.map(|(min, max)| (min.0..=max.0, min.1..=max.1))
+    }
+
+    fn extend_bounds(&mut self, pos: Point) {
+        let (min, max) = self.bounds.get_or_insert((pos, pos));
+        *min = (min.0.min(pos.0), min.1.min(pos.1));
+        *max = (max.0.max(pos.0), max.1.max(pos.1));
+    }
+
+    fn neighbors(&self, pos: Point) -> impl Iterator<Item = Point> + use<T> {
+        self.neighborhood
+            .offsets(pos.0.rem_euclid(2) == 1)
+            .to_vec()
+            .into_iter()
+            .map(move |(dr, dc)| (pos.0 + dr as i64, pos.1 + dc as i64))
+    }
+}
+
+impl<T: TilePath> SparseGrid<T> {
+    /// The fewest moves from `source` to `target`, or `None` if it takes more than
+    /// `max_distance`. The grid has no edges, so a walled-off target is only given up on once
+    /// every cell within `max_distance` has been tried.
+    pub fn shortest_path(
+        &mut self,
+        source: Point,
+        target: Point,
+        max_distance: usize,
+    ) -> Option<usize> {
+        self.shortest_route(source, target, max_distance)
+            .map(|route| route.distance)
+    }
+
+    /// Like [`SparseGrid::shortest_path`], with the cells along the way.
+    pub fn shortest_route(
+        &mut self,
+        source: Point,
+        target: Point,
+        max_distance: usize,
+    ) -> Option<Route<Point>> {
+        let (distances, previous) = self.explore(source, Some(target), max_distance);
+        Some(Route {
+            distance: *distances.get(&target)?,
+            cells: trace_back(|pos| previous.get(&pos).copied(), target),
+        })
+    }
+
+    /// Every cell at most `max_distance` moves from `source`, with its distance, nearest first.
+    pub fn reachable(&mut self, source: Point, max_distance: usize) -> Vec<(Point, usize)> {
+        let (distances, _) = self.explore(source, None, max_distance);
+        let mut reached = distances.into_iter().collect::<Vec<_>>();
+        reached.sort_by_key(|&(pos, distance)| (distance, pos));
+        reached
+    }
+
+    /// Breadth-first search from `source`, until it reaches `target` or runs out of cells within
+    /// `max_distance`. Returns the distance to, and the previous cell of, every cell reached.
+    fn explore(
+        &mut self,
+        source: Point,
+        target: Option<Point>,
+        max_distance: usize,
+    ) -> (HashMap<Point, usize>, HashMap<Point, Point>) {
+        let mut distances = HashMap::from([(source, 0)]);
+        let mut previous = HashMap::new();
+        let mut pending = VecDeque::from([source]);
+        while let Some(pos) = pending.pop_front() {
+            let distance = distances[&pos];
+            if Some(pos) == target {
+                break;
+            }
+            if distance == max_distance {
+                continue;
+            }
+            for next in self.neighbors(pos) {
+                if !distances.contains_key(&next) && self.get(next).is_passable() {
+                    distances.insert(next, distance + 1);
+                    previous.insert(next, pos);
+                    pending.push_back(next);
+                }
+            }
+        }
+        (distances, previous)
+    }
+}
+
+impl<T: Debug> Debug for SparseGrid<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SparseGrid")
+            .field("tiles", &self.tiles)
+            .field("bounds", &self.bounds)
+            .field("neighborhood", &self.neighborhood)
+            .finish_non_exhaustive()
+    }
+}
+
 pub fn permute<T, F: FnMut(&[T])>(items: &mut [T], callback: &mut F) {
     fn inner<T, F: FnMut(&[T])>(items: &mut [T], index: usize, callback: &mut F) {
         if index == items.len() {
@@ -214,3 +726,258 @@ pub fn permute<T, F: FnMut(&[T])>(items: &mut [T], callback: &mut F) {
     }
     inner(items, 0, callback);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A digit is the cost of entering the tile, and `#` is a wall.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Terrain(u8);
+
+    impl TryFrom<u8> for Terrain {
+        type Error = u8;
+
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            match value {
+                b'#' | b'0'..=b'9' => Ok(Self(value)),
+                _ => Err(value),
+            }
+        }
+    }
+
+    impl TilePath for Terrain {
+        fn is_passable(&self) -> bool {
+            self.0 != b'#'
+        }
+    }
+
+    impl TileCost for Terrain {
+        fn cost(&self) -> Option<usize> {
+            self.0.is_ascii_digit().then(|| usize::from(self.0 - b'0'))
+        }
+    }
+
+    /// Like [`Terrain`], without free tiles.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Toll(u8);
+
+    impl TryFrom<u8> for Toll {
+        type Error = u8;
+
+        fn try_from(value: u8) -> Result<Self, Self::Error> {
+            match value {
+                b'#' | b'1'..=b'9' => Ok(Self(value)),
+                _ => Err(value),
+            }
+        }
+    }
+
+    impl TilePath for Toll {
+        fn is_passable(&self) -> bool {
+            self.0 != b'#'
+        }
+    }
+
+    impl TileCost for Toll {
+        const MIN_COST: usize = 1;
+
+        fn cost(&self) -> Option<usize> {
+            self.0.is_ascii_digit().then(|| usize::from(self.0 - b'0'))
+        }
+    }
+
+    const TERRAIN: &str = "\
+        11111\n\
+        19991\n\
+        11#91\n\
+        91#11\
+    ";
+
+    #[test]
+    fn test_weighted_paths() {
+        let grid: Grid<Terrain> = TERRAIN.parse().unwrap();
+        let expected = Route {
+            distance: 12,
+            cells: vec![
+                (3, 1),
+                (2, 1),
+                (2, 0),
+                (1, 0),
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (0, 4),
+                (1, 4),
+                (2, 4),
+                (3, 4),
+                (3, 3),
+            ],
+        };
+        assert_eq!(grid.dijkstra((3, 1), (3, 3)), Some(expected.clone()));
+        assert_eq!(grid.a_star((3, 1), (3, 3)), Some(expected));
+        assert_eq!(grid.dijkstra((0, 0), (2, 2)), None);
+        let trivial = grid.a_star_with((1, 1), (1, 1), |_| 100).unwrap();
+        assert_eq!(trivial.distance, 0);
+        assert_eq!(trivial.cells, [(1, 1)]);
+
+        // Going around through the free tiles is cheaper, though it takes more moves
+        let grid: Grid<Terrain> = "121\n0#0\n0#0\n000".parse().unwrap();
+        assert_eq!(grid.dijkstra((0, 0), (0, 2)).unwrap().distance, 1);
+        assert_eq!(grid.a_star((0, 0), (0, 2)).unwrap().distance, 1);
+        let guess = grid.a_star_with((0, 0), (0, 2), |pos| manhattan(pos, (0, 2)));
+        assert_eq!(guess.unwrap().distance, 3);
+    }
+
+    #[test]
+    fn test_a_star_with_min_cost() {
+        let grid: Grid<Toll> = TERRAIN.parse().unwrap();
+        let cells: Vec<_> = (0..grid.rows())
+            .flat_map(|row| (0..grid.cols()).map(move |col| (row, col)))
+            .collect();
+        for (neighborhood, topology) in [
+            (Neighborhood::Orthogonal, Topology::Bounded),
+            (Neighborhood::Moore, Topology::Bounded),
+            (Neighborhood::Orthogonal, Topology::Torus),
+            (Neighborhood::Moore, Topology::Torus),
+        ] {
+            let grid = grid
+                .clone()
+                .with_neighborhood(neighborhood)
+                .with_topology(topology);
+            for &source in &cells {
+                for &target in &cells {
+                    let distance = |route: Option<Route>| route.map(|route| route.distance);
+                    assert_eq!(
+                        distance(grid.a_star(source, target)),
+                        distance(grid.dijkstra(source, target)),
+                        "{source:?} to {target:?} on {:?} {:?}",
+                        grid.neighborhood,
+                        grid.topology,
+                    );
+                }
+            }
+        }
+    }
+
+    const CHECKERED: &str = "\
+        1#1\n\
+        #1#\n\
+        1#1\
+    ";
+
+    #[test]
+    fn test_neighborhoods() {
+        let grid: Grid<Terrain> = CHECKERED.parse().unwrap();
+        assert_eq!(grid.shortest_path((0, 0), (2, 2)), None);
+
+        let grid = grid.with_neighborhood(Neighborhood::Moore);
+        assert_eq!(grid.shortest_path((0, 0), (2, 2)), Some(2));
+        assert_eq!(grid.a_star((0, 0), (2, 2)).unwrap().distance, 2);
+
+        // (1, 1) is on an odd row, so it touches (0, 2) and (2, 2) but not (0, 0)
+        let grid = grid.with_neighborhood(Neighborhood::Hex);
+        assert_eq!(grid.shortest_path((1, 1), (2, 2)), Some(1));
+        assert_eq!(grid.shortest_path((1, 1), (0, 2)), Some(1));
+        assert_eq!(grid.shortest_path((1, 1), (0, 0)), None);
+
+        let knight = [
+            (-2, -1),
+            (-2, 1),
+            (-1, -2),
+            (-1, 2),
+            (1, -2),
+            (1, 2),
+            (2, -1),
+            (2, 1),
+        ];
+        let grid: Grid<Terrain> = "111\n111\n111".parse().unwrap();
+        let grid = grid.with_neighborhood(Neighborhood::Custom(knight.to_vec()));
+        assert_eq!(grid.shortest_path((0, 0), (2, 1)), Some(1));
+        assert_eq!(grid.shortest_path((0, 0), (0, 1)), Some(3));
+        assert_eq!(grid.shortest_path((0, 0), (1, 1)), None);
+    }
+
+    #[test]
+    fn test_topologies() {
+        let grid: Grid<Terrain> = CHECKERED.parse().unwrap();
+        let grid = grid.with_topology(Topology::Torus);
+        let route = grid.shortest_route((0, 0), (2, 2)).unwrap();
+        assert_eq!(route.cells, [(0, 0), (2, 0), (2, 2)]);
+        assert_eq!(grid.a_star((0, 0), (2, 2)).unwrap().distance, 2);
+
+        let grid: Grid<Terrain> = "11#".parse().unwrap();
+        assert_eq!(grid.shortest_path((0, 0), (0, 2)), None);
+        let grid = grid.with_topology(Topology::Unbounded(Terrain(b'1')));
+        assert_eq!(grid.get((5, 5)), Some(&Terrain(b'1')));
+        let route = grid.shortest_route((0, 0), (0, 5)).unwrap();
+        assert_eq!(route.distance, 7);
+        assert_eq!(route.cells[2..5], [(1, 1), (1, 2), (1, 3)]);
+        assert_eq!(grid.dijkstra((0, 0), (0, 5)).unwrap().distance, 7);
+
+        // The wall can not be entered, even with the whole plane to search
+        assert_eq!(grid.shortest_path((0, 0), (0, 2)), None);
+        assert_eq!(grid.shortest_route((0, 0), (0, 2)), None);
+        assert_eq!(grid.dijkstra((0, 0), (0, 2)), None);
+        assert_eq!(grid.a_star((0, 0), (0, 2)), None);
+        let grid: Grid<Terrain> = "1#1\n###\n1#1".parse().unwrap();
+        let grid = grid.with_topology(Topology::Unbounded(Terrain(b'1')));
+        assert_eq!(grid.shortest_path((0, 0), (2, 2)), None);
+        assert_eq!(grid.shortest_path((2, 2), (0, 2)), Some(4));
+
+        // Searches for every target end two moves past the grid
+        let grid: Grid<Terrain> = "11#".parse().unwrap();
+        let grid = grid.with_topology(Topology::Unbounded(Terrain(b'1')));
+        assert_eq!(grid.all_shortest_paths((0, 0), |_| true).count(), 14);
+        let (route, _) = grid.all_shortest_routes((0, 0), |_| true).last().unwrap();
+        assert_eq!(route.distance, 6);
+    }
+
+    #[test]
+    fn test_sparse_grid() {
+        // A wall along row 0, with a gap at column 3
+        let mut grid = SparseGrid::new(|(row, col)| row != 0 || col == 3);
+        assert_eq!(grid.bounds(), None);
+        assert_eq!(grid.shortest_path((-1, 0), (1, 0), 8), Some(8));
+        assert_eq!(grid.shortest_path((-1, 0), (1, 0), 7), None);
+        let route = grid.shortest_route((-1, 0), (1, 0), 100).unwrap();
+        assert_eq!(route.cells[3..6], [(-1, 3), (0, 3), (1, 3)]);
+        let (rows, cols) = grid.bounds().unwrap();
+        assert!(rows.contains(&-1) && rows.contains(&1) && cols.contains(&3));
+
+        grid.insert((0, 3), false);
+        let reached = grid.reachable((-1, 0), 2);
+        assert_eq!(reached.len(), 9);
+        assert_eq!(reached[0], ((-1, 0), 0));
+        assert!(reached.iter().all(|&((row, _), _)| row < 0));
+
+        let mut grid = SparseGrid::filled(true).with_neighborhood(Neighborhood::Moore);
+        assert_eq!(grid.shortest_path((0, 0), (-5, 3), 100), Some(5));
+        assert_eq!(grid.reachable((0, 0), 1).len(), 9);
+
+        // A ring of walls around the target
+        let mut grid = SparseGrid::new(|(row, col): Point| {
+            (row, col) == (5, 5) || (row - 5).abs().max((col - 5).abs()) != 1
+        });
+        assert_eq!(grid.shortest_path((0, 0), (5, 5), 100), None);
+        assert_eq!(grid.shortest_route((0, 0), (5, 5), 100), None);
+        assert_eq!(grid.shortest_path((0, 0), (7, 7), 100), Some(14));
+    }
+
+    #[test]
+    fn test_reachable() {
+        let grid: Grid<Terrain> = TERRAIN.parse().unwrap();
+        let reached = grid.reachable((3, 3), 2);
+        assert_eq!(
+            reached,
+            [
+                ((3, 3), 0),
+                ((2, 3), 1),
+                ((3, 4), 1),
+                ((1, 3), 2),
+                ((2, 4), 2)
+            ]
+        );
+    }
+}
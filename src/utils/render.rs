@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+use super::{Grid, Route};
+
+/// How a tile is drawn by the [`Display`] of a [`Grid`].
+pub trait Glyph {
+    fn glyph(&self) -> char;
+}
+
+impl Glyph for bool {
+    fn glyph(&self) -> char {
+        if *self { '#' } else { '.' }
+    }
+}
+
+impl<T: Glyph> Display for Grid<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.render(T::glyph).fmt(f)
+    }
+}
+
+impl<T> Grid<T> {
+    /// Draws every tile as the character returned by `glyph`.
+    pub fn render<'a>(&'a self, glyph: impl Fn(&T) -> char + 'a) -> Render<'a, T> {
+        Render {
+            grid: self,
+            glyph: Box::new(glyph),
+            overlay: HashMap::new(),
+        }
+    }
+}
+
+/// A picture of a [`Grid`], one glyph per cell, with one line of text per row.
+///
+/// The images and the half-block text are drawn from the same glyphs, so a cell that is
+/// highlighted in the text is highlighted in those as well.
+pub struct Render<'a, T> {
+    grid: &'a Grid<T>,
+    glyph: Box<dyn Fn(&T) -> char + 'a>,
+    overlay: HashMap<(usize, usize), char>,
+}
+
+impl<T> Render<'_, T> {
+    /// Draws `glyph` over the given cells, in place of their tiles.
+    #[must_use]
+    pub fn with_cells(
+        mut self,
+        cells: impl IntoIterator<Item = (usize, usize)>,
+        glyph: char,
+    ) -> Self {
+        self.overlay
+            .extend(cells.into_iter().map(|pos| (pos, glyph)));
+        self
+    }
+
+    /// Draws `glyph` over every cell of the route.
+    #[must_use]
+    pub fn with_route(self, route: &Route, glyph: char) -> Self {
+        self.with_cells(route.cells.iter().copied(), glyph)
+    }
+
+    fn glyph_at(&self, pos: (usize, usize)) -> char {
+        self.overlay
+            .get(&pos)
+            .copied()
+            .unwrap_or_else(|| (self.glyph)(&self.grid[pos]))
+    }
+
+    fn glyphs(&self) -> impl Iterator<Item = char> + '_ {
+        (0..self.grid.rows())
+            .flat_map(move |row| (0..self.grid.cols()).map(move |col| (row, col)))
+            .map(|pos| self.glyph_at(pos))
+    }
+
+    /// Draws two rows on each line of text, with block characters for the cells where `is_set`
+    /// returns true. A grid with an odd number of rows is padded with a row of unset cells.
+    pub fn half_blocks(&self, is_set: impl Fn(char) -> bool) -> String {
+        let is_set = |row, col| row < self.grid.rows() && is_set(self.glyph_at((row, col)));
+        let mut text = String::new();
+        for row in (0..self.grid.rows()).step_by(2) {
+            for col in 0..self.grid.cols() {
+                text.push(match (is_set(row, col), is_set(row + 1, col)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Writes a binary PGM image, one pixel per cell, with the gray level `shade` gives each
+    /// glyph.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing.
+    pub fn write_pgm(&self, mut writer: impl Write, shade: impl Fn(char) -> u8) -> io::Result<()> {
+        write!(
+            writer,
+            "P5\n{} {}\n255\n",
+            self.grid.cols(),
+            self.grid.rows()
+        )?;
+        let pixels = self.glyphs().map(shade).collect::<Vec<_>>();
+        writer.write_all(&pixels)
+    }
+
+    /// Writes a binary PPM image, one pixel per cell, with the RGB color `color` gives each
+    /// glyph.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing.
+    pub fn write_ppm(
+        &self,
+        mut writer: impl Write,
+        color: impl Fn(char) -> [u8; 3],
+    ) -> io::Result<()> {
+        write!(
+            writer,
+            "P6\n{} {}\n255\n",
+            self.grid.cols(),
+            self.grid.rows()
+        )?;
+        let pixels = self.glyphs().flat_map(color).collect::<Vec<_>>();
+        writer.write_all(&pixels)
+    }
+}
+
+impl<T> Display for Render<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for row in 0..self.grid.rows() {
+            for col in 0..self.grid.cols() {
+                write!(f, "{}", self.glyph_at((row, col)))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open cells are `true`.
+    const MAZE: [[bool; 5]; 3] = [
+        [true, true, false, true, true],
+        [false, true, false, true, false],
+        [true, true, true, true, true],
+    ];
+
+    #[test]
+    fn test_text() {
+        let grid = Grid::from(MAZE);
+        assert_eq!(grid.to_string(), "##.##\n.#.#.\n#####\n");
+
+        let route = grid.shortest_route((0, 0), (0, 4)).unwrap();
+        let text = grid
+            .render(|&open| if open { ' ' } else { '#' })
+            .with_cells([(2, 0)], '?')
+            .with_route(&route, 'o')
+            .to_string();
+        assert_eq!(text, "oo#oo\n#o#o#\n?ooo \n");
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let grid = Grid::from(MAZE);
+        let render = grid.render(bool::glyph);
+        assert_eq!(render.half_blocks(|glyph| glyph == '#'), "▀█ █▀\n▀▀▀▀▀\n");
+        let render = render.with_cells([(2, 2)], 'x');
+        assert_eq!(render.half_blocks(|glyph| glyph == '#'), "▀█ █▀\n▀▀ ▀▀\n");
+    }
+
+    #[test]
+    fn test_images() {
+        let grid = Grid::from([[true, false, true], [false, false, true]]);
+        let render = grid.render(bool::glyph).with_cells([(1, 0)], 'x');
+
+        let mut pgm = Vec::new();
+        render
+            .write_pgm(&mut pgm, |glyph| match glyph {
+                '#' => 0,
+                'x' => 128,
+                _ => 255,
+            })
+            .unwrap();
+        assert_eq!(pgm, b"P5\n3 2\n255\n\x00\xff\x00\x80\xff\x00");
+
+        let mut ppm = Vec::new();
+        render
+            .write_ppm(&mut ppm, |glyph| match glyph {
+                '#' => [0, 0, 0],
+                'x' => [255, 0, 0],
+                _ => [255, 255, 255],
+            })
+            .unwrap();
+        assert_eq!(&ppm[..11], b"P6\n3 2\n255\n");
+        assert_eq!(ppm.len(), 11 + 3 * 6);
+        assert_eq!(ppm[11 + 9..11 + 12], [255, 0, 0]);
+    }
+}